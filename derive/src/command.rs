@@ -0,0 +1,332 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    ext::IdentExt,
+    meta::ParseNestedMeta,
+    Data,
+    DeriveInput,
+    Expr,
+    Field,
+    Fields,
+    GenericArgument,
+    LitStr,
+    Path,
+    PathArguments,
+    Token,
+    Type,
+};
+
+#[derive(Default)]
+struct CommandAttrs {
+    validate: Option<Path>,
+}
+
+impl CommandAttrs {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+
+        for attr in input.attrs.iter() {
+            if !attr.path().is_ident("command") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("validate") {
+                    let path: LitStr = meta.value()?.parse()?;
+
+                    attrs.validate = Some(path.parse()?);
+                } else {
+                    return Err(meta.error("unsupported command attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(attrs)
+    }
+}
+
+enum Kind {
+    Positional,
+    /// `KEYWORD value`, optional.
+    Keyword(LitStr),
+    /// `KEYWORD` alone, a `bool`.
+    Flag(LitStr),
+    /// Every argument left, a `Vec`.
+    Rest,
+}
+
+enum Parse {
+    FromStr,
+    Bytes,
+    Millis,
+}
+
+struct FieldAttrs {
+    kind: Kind,
+    parse: Parse,
+    default: Option<Expr>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field, name: &str) -> syn::Result<Self> {
+        let mut attrs = Self {
+            kind: Kind::Positional,
+            parse: Parse::FromStr,
+            default: None,
+        };
+
+        for attr in field.attrs.iter() {
+            if !attr.path().is_ident("command") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("keyword") {
+                    attrs.kind = Kind::Keyword(keyword(&meta, name)?);
+                } else if meta.path.is_ident("flag") {
+                    attrs.kind = Kind::Flag(keyword(&meta, name)?);
+                } else if meta.path.is_ident("rest") {
+                    attrs.kind = Kind::Rest;
+                } else if meta.path.is_ident("bytes") {
+                    attrs.parse = Parse::Bytes;
+                } else if meta.path.is_ident("millis") {
+                    attrs.parse = Parse::Millis;
+                } else if meta.path.is_ident("default") {
+                    let default: LitStr = meta.value()?.parse()?;
+
+                    attrs.default = Some(default.parse()?);
+                } else {
+                    return Err(meta.error("unsupported command field attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        if attrs.default.is_some() && !matches!(attrs.kind, Kind::Keyword(_)) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "default only applies to keyword fields",
+            ));
+        }
+
+        Ok(attrs)
+    }
+}
+
+/// Keyword of a field, its upper case name unless set.
+fn keyword(meta: &ParseNestedMeta, name: &str) -> syn::Result<LitStr> {
+    if meta.input.peek(Token![=]) {
+        return meta.value()?.parse();
+    }
+
+    let ident = meta.path.get_ident().expect("keyword or flag");
+
+    Ok(LitStr::new(&name.to_ascii_uppercase(), ident.span()))
+}
+
+/// `T` of `Option<T>` or `Vec<T>`.
+fn inner_type<'t>(ty: &'t Type, wrapper: &str) -> Option<&'t Type> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    if segment.ident != wrapper {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_value(parse: &Parse, ty: &Type, name: &str) -> TokenStream {
+    match parse {
+        Parse::FromStr => quote! { args.parse::<#ty>(#name)? },
+        Parse::Bytes => quote! { args.bytes(#name)? },
+        Parse::Millis => quote! { args.millis(#name)? },
+    }
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let attrs = CommandAttrs::parse(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Command can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Command can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut bindings = Vec::new();
+    let mut keywords = Vec::new();
+    let mut rest = None;
+    let mut idents = Vec::new();
+    let mut values = Vec::new();
+    let mut usage = Vec::new();
+
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().expect("named field");
+        let name = ident.unraw().to_string();
+        let attrs = FieldAttrs::parse(field, &name)?;
+        let ty = &field.ty;
+
+        match &attrs.kind {
+            Kind::Positional => {
+                if !keywords.is_empty() || rest.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "positional fields come before keyword, flag and rest fields",
+                    ));
+                }
+
+                let value = parse_value(&attrs.parse, ty, &name);
+
+                bindings.push(quote! { let #ident = #value; });
+                values.push(quote! { #ident });
+                usage.push(format!("<{}>", name));
+            }
+            Kind::Keyword(keyword) => {
+                let (inner, value) = match (inner_type(ty, "Option"), &attrs.default) {
+                    (Some(inner), None) => (inner, quote! { #ident }),
+                    (None, Some(default)) => (ty, quote! { #ident.unwrap_or_else(|| #default) }),
+                    (Some(_), Some(_)) => {
+                        return Err(syn::Error::new_spanned(
+                            ty,
+                            "keyword fields with a default are not an Option",
+                        ))
+                    }
+                    (None, None) => {
+                        return Err(syn::Error::new_spanned(
+                            field,
+                            "keyword fields are an Option or have a default",
+                        ))
+                    }
+                };
+
+                let parse = parse_value(&attrs.parse, inner, &keyword.value());
+
+                keywords.push(quote! {
+                    if args.keyword(#keyword) {
+                        #ident = ::std::option::Option::Some(#parse);
+                        continue;
+                    }
+                });
+                bindings.push(quote! {
+                    let mut #ident: ::std::option::Option<#inner> = ::std::option::Option::None;
+                });
+                values.push(value);
+                usage.push(format!("[{} <{}>]", keyword.value(), name));
+            }
+            Kind::Flag(keyword) => {
+                keywords.push(quote! {
+                    if args.keyword(#keyword) {
+                        #ident = true;
+                        continue;
+                    }
+                });
+                bindings.push(quote! { let mut #ident = false; });
+                values.push(quote! { #ident });
+                usage.push(format!("[{}]", keyword.value()));
+            }
+            Kind::Rest => {
+                if rest.is_some() {
+                    return Err(syn::Error::new_spanned(field, "only one field can be rest"));
+                }
+
+                let inner = inner_type(ty, "Vec")
+                    .ok_or_else(|| syn::Error::new_spanned(field, "rest fields are a Vec"))?;
+
+                let parse = parse_value(&attrs.parse, inner, &name);
+
+                rest = Some(quote! {
+                    let mut #ident = ::std::vec::Vec::new();
+
+                    while !args.is_empty() {
+                        #ident.push(#parse);
+                    }
+                });
+                values.push(quote! { #ident });
+                usage.push(format!("[<{}> ...]", name));
+            }
+        }
+
+        idents.push(ident);
+    }
+
+    let keywords = (!keywords.is_empty()).then(|| {
+        quote! {
+            loop {
+                #( #keywords )*
+
+                break;
+            }
+        }
+    });
+
+    let usage = usage.join(" ");
+
+    let validate = attrs.validate.map(|path| {
+        quote! {
+            fn validate(&self) -> ::std::result::Result<(), ::redismod::__rm::RedisError> {
+                #path(self)
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Arguments after the command name, as parsed by `TryFrom`.
+            pub const USAGE: &'static str = #usage;
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<
+            ::std::vec::Vec<::redismod::__rm::RedisString>
+        > for #ident #ty_generics #where_clause {
+            type Error = ::redismod::__rm::RedisError;
+
+            fn try_from(
+                args: ::std::vec::Vec<::redismod::__rm::RedisString>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let mut args = ::redismod::CommandArgs::new(args);
+
+                #( #bindings )*
+
+                #keywords
+
+                #rest
+
+                args.finish()?;
+
+                ::std::result::Result::Ok(Self {
+                    #( #idents: #values, )*
+                })
+            }
+        }
+
+        impl #impl_generics ::redismod::Command for #ident #ty_generics #where_clause {
+            #validate
+        }
+    })
+}
@@ -0,0 +1,376 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Expr, Field, Fields, LitStr, Path, Token, Variant};
+
+#[derive(Default)]
+struct ConfigAttrs {
+    validate: Option<Path>,
+}
+
+impl ConfigAttrs {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+
+        for attr in input.attrs.iter() {
+            if !attr.path().is_ident("config") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("validate") {
+                    let path: LitStr = meta.value()?.parse()?;
+
+                    attrs.validate = Some(path.parse()?);
+                } else {
+                    return Err(meta.error("unsupported config attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(attrs)
+    }
+}
+
+enum FieldDefault {
+    /// Field required unless its type has a missing value, e.g. `Option`.
+    None,
+    /// `Default::default()`.
+    Trait,
+    /// Parsed like an argument value.
+    Value(LitStr),
+}
+
+struct FieldAttrs {
+    name: Option<LitStr>,
+    default: FieldDefault,
+    option: Option<LitStr>,
+    immutable: bool,
+    min: Option<Expr>,
+    max: Option<Expr>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut attrs = Self {
+            name: None,
+            default: FieldDefault::None,
+            option: None,
+            immutable: false,
+            min: None,
+            max: None,
+        };
+
+        for attr in field.attrs.iter() {
+            if !attr.path().is_ident("config") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    attrs.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default") {
+                    attrs.default = if meta.input.peek(Token![=]) {
+                        FieldDefault::Value(meta.value()?.parse()?)
+                    } else {
+                        FieldDefault::Trait
+                    };
+                } else if meta.path.is_ident("option") {
+                    let name = if meta.input.peek(Token![=]) {
+                        meta.value()?.parse()?
+                    } else {
+                        let ident = field.ident.as_ref().expect("named field");
+
+                        LitStr::new(&ident.to_string().replace('_', "-"), ident.span())
+                    };
+
+                    attrs.option = Some(name);
+                } else if meta.path.is_ident("immutable") {
+                    attrs.immutable = true;
+                } else if meta.path.is_ident("min") {
+                    attrs.min = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("max") {
+                    attrs.max = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unsupported config field attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        if attrs.immutable && attrs.option.is_none() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "immutable only applies to fields registered with option",
+            ));
+        }
+
+        if (attrs.min.is_some() || attrs.max.is_some()) && attrs.option.is_none() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "min and max only apply to fields registered with option",
+            ));
+        }
+
+        Ok(attrs)
+    }
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let attrs = ConfigAttrs::parse(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Config can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Config can only be derived for structs, use ConfigValue for enums",
+            ))
+        }
+    };
+
+    let mut keywords = Vec::new();
+    let mut values = Vec::new();
+    let mut options = Vec::new();
+
+    for field in fields.iter() {
+        let attrs = FieldAttrs::parse(field)?;
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+
+        let keyword = attrs
+            .name
+            .unwrap_or_else(|| LitStr::new(&ident.to_string().to_ascii_uppercase(), ident.span()));
+
+        let value = match &attrs.default {
+            FieldDefault::Value(default) => quote! {
+                values.next().flatten().or_else(|| {
+                    ::std::option::Option::Some(::std::string::ToString::to_string(#default))
+                })
+            },
+            _ => quote! { values.next().flatten() },
+        };
+
+        let default = match &attrs.default {
+            FieldDefault::Trait => quote! {
+                || ::std::option::Option::Some(::std::default::Default::default())
+            },
+            _ => quote! { || ::std::option::Option::None },
+        };
+
+        values.push(quote! {
+            #ident: ::redismod::__config_field::<#ty, _>(#keyword, #value, #default)?
+        });
+
+        if let Some(name) = attrs.option {
+            let immutable = attrs.immutable.then(|| quote! { .immutable() });
+            let bound = |bound: Option<Expr>| match bound {
+                Some(bound) => quote! { ::std::option::Option::Some((#bound) as i64) },
+                None => quote! { ::std::option::Option::None },
+            };
+            let (min, max) = (bound(attrs.min), bound(attrs.max));
+
+            options.push(quote! {
+                <#ty as ::redismod::ConfigValue>::option::<Self>(
+                    #name,
+                    #min,
+                    #max,
+                    |config: &Self| &config.#ident,
+                    |config: &mut Self| &mut config.#ident,
+                )
+                #immutable
+            });
+        }
+
+        keywords.push(keyword);
+    }
+
+    let validate = attrs.validate.map(|path| {
+        quote! {
+            fn validate(&self) -> ::std::result::Result<(), ::redismod::__rm::RedisError> {
+                #path(self)
+            }
+        }
+    });
+
+    let options = (!options.is_empty()).then(|| {
+        quote! {
+            fn options() -> ::std::vec::Vec<::redismod::ConfigOption<Self>> {
+                ::std::vec![#( #options ),*]
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::std::convert::TryFrom<
+            ::std::vec::Vec<::redismod::__rm::RedisString>
+        > for #ident #ty_generics #where_clause {
+            type Error = ::redismod::__rm::RedisError;
+
+            fn try_from(
+                args: ::std::vec::Vec<::redismod::__rm::RedisString>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let mut values = ::redismod::__config_args(args, &[#( #keywords ),*])?.into_iter();
+
+                ::std::result::Result::Ok(Self {
+                    #( #values, )*
+                })
+            }
+        }
+
+        impl #impl_generics ::redismod::Config for #ident #ty_generics #where_clause {
+            #validate
+
+            #options
+        }
+    })
+}
+
+fn variant_name(variant: &Variant) -> syn::Result<LitStr> {
+    let mut name = None;
+
+    for attr in variant.attrs.iter() {
+        if !attr.path().is_ident("config") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported config variant attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let ident = &variant.ident;
+
+    Ok(name.unwrap_or_else(|| LitStr::new(&kebab_case(&ident.to_string()), ident.span())))
+}
+
+/// `RoundRobin` as `round-robin`.
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::new();
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+
+        kebab.push(c.to_ascii_lowercase());
+    }
+
+    kebab
+}
+
+pub fn expand_value(input: DeriveInput) -> syn::Result<TokenStream> {
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ConfigValue can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut idents = Vec::new();
+    let mut names = Vec::new();
+
+    for variant in variants.iter() {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "ConfigValue can only be derived for enums without fields",
+            ));
+        }
+
+        idents.push(&variant.ident);
+        names.push(variant_name(variant)?);
+    }
+
+    let expected = names
+        .iter()
+        .map(LitStr::value)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let indexes = (0..names.len() as i32).collect::<Vec<_>>();
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::redismod::ConfigValue for #ident #ty_generics #where_clause {
+            fn parse_value(
+                value: &str,
+            ) -> ::std::result::Result<Self, ::std::string::String> {
+                #(
+                    if value.eq_ignore_ascii_case(#names) {
+                        return ::std::result::Result::Ok(Self::#idents);
+                    }
+                )*
+
+                ::std::result::Result::Err(::std::format!(
+                    "expected one of {}, got {}",
+                    #expected,
+                    value,
+                ))
+            }
+
+            fn format_value(&self) -> ::std::string::String {
+                let name = match self {
+                    #( Self::#idents => #names, )*
+                };
+
+                ::std::string::ToString::to_string(name)
+            }
+
+            fn option<C: 'static>(
+                name: &'static str,
+                _min: ::std::option::Option<i64>,
+                _max: ::std::option::Option<i64>,
+                get: fn(&C) -> &Self,
+                get_mut: fn(&mut C) -> &mut Self,
+            ) -> ::redismod::ConfigOption<C> {
+                const VALUES: &[(&str, i32)] = &[#( (#names, #indexes) ),*];
+
+                ::redismod::ConfigOption::enumeration(
+                    name,
+                    VALUES,
+                    move |config| match get(config) {
+                        #( Self::#idents => #indexes, )*
+                    },
+                    move |config, value| {
+                        *get_mut(config) = match value {
+                            #( #indexes => Self::#idents, )*
+                            _ => {
+                                return ::std::result::Result::Err(::std::format!(
+                                    "unknown value {}",
+                                    value,
+                                ))
+                            }
+                        };
+
+                        ::std::result::Result::Ok(())
+                    },
+                )
+            }
+        }
+    })
+}
@@ -0,0 +1,112 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Field, Fields, GenericParam};
+
+/// Whether `field` is marked with `#[heap_size(skip)]`, also honoured by the `RedisType` derive.
+pub(crate) fn skipped(field: &Field) -> syn::Result<bool> {
+    let mut skip = false;
+
+    for attr in field.attrs.iter() {
+        if !attr.path().is_ident("heap_size") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+            } else {
+                return Err(meta.error("unsupported heap_size attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(skip)
+}
+
+/// Destructuring pattern of `fields` and the sum of their heap sizes.
+fn expand_fields(fields: &Fields) -> syn::Result<(TokenStream, TokenStream)> {
+    let mut bindings = Vec::new();
+    let mut counted = Vec::new();
+
+    for (index, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__field{}", index);
+
+        if !skipped(field)? {
+            counted.push(binding.clone());
+        }
+
+        bindings.push((field.ident.as_ref(), binding));
+    }
+
+    let pattern = match fields {
+        Fields::Named(_) => {
+            let bindings = bindings
+                .iter()
+                .map(|(ident, binding)| quote!(#ident: #binding));
+
+            quote!({ #( #bindings, )* })
+        }
+        Fields::Unnamed(_) => {
+            let bindings = bindings.iter().map(|(_, binding)| binding);
+
+            quote!(( #( #bindings, )* ))
+        }
+        Fields::Unit => quote!(),
+    };
+
+    let sum = quote!(0 #( + ::redismod::HeapSize::heap_size(#counted) )*);
+
+    Ok((pattern, sum))
+}
+
+pub fn expand(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+
+    let arms = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, sum) = expand_fields(&data.fields)?;
+
+            vec![quote!(Self #pattern => #sum)]
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+
+            for variant in data.variants.iter() {
+                let variant_ident = &variant.ident;
+                let (pattern, sum) = expand_fields(&variant.fields)?;
+
+                arms.push(quote!(Self::#variant_ident #pattern => #sum));
+            }
+
+            arms
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "HeapSize cannot be derived for unions",
+            ))
+        }
+    };
+
+    for param in input.generics.params.iter_mut() {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(::redismod::HeapSize));
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::redismod::HeapSize for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn heap_size(&self) -> usize {
+                match self {
+                    #( #arms, )*
+                }
+            }
+        }
+    })
+}
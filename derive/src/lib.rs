@@ -0,0 +1,130 @@
+mod command;
+mod config;
+mod heap_size;
+mod redis_type;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Implements `redismod::Type` for a struct with named fields.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, RedisType)]
+/// #[redis_type(name = "task", prefix = "t", redis_name = "taskver10", version = 1, id = "id")]
+/// pub struct Task {
+///     #[heap_size(skip)]
+///     pub id: xid::Id,
+///     pub payload: Vec<u8>,
+/// }
+/// ```
+///
+/// The struct is persisted with `redismod::to_saver_marked`/`from_loader_marked`, so it has
+/// to implement `Serialize` and `Deserialize`. A value failing to serialize is logged and
+/// saved as a marker, failing its load instead of misreading the next keys. Memory usage is
+/// `size_of::<Self>()` plus the `redismod::HeapSize` of every field not marked with
+/// `#[heap_size(skip)]`, like for the `HeapSize` derive.
+///
+/// Older rdb layouts are handled by `migrations = "redismod::MigrateFrom<TaskV1>"`.
+///
+/// Optional `Type` hooks are forwarded to functions with the same signature:
+/// `aof_rewrite`, `free_effort`, `unlink`, `digest`, `copy`, `defrag`, `indexes` and
+/// `default_ttl`, e.g. `aof_rewrite = "Task::aof_rewrite"`, which also sets
+/// `HAS_AOF_REWRITE`. A bare `copy` implements `COPY` with `Clone`.
+///
+/// `HashMap` and `HashSet` fields need a `digest` hook, the default digest follows the
+/// serialization order, which differs between master and replica for hash containers.
+#[proc_macro_derive(RedisType, attributes(redis_type, heap_size))]
+pub fn derive_redis_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    redis_type::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `redismod::HeapSize` as the sum of the field heap sizes.
+///
+/// Fields marked with `#[heap_size(skip)]` are not counted, which is handy for
+/// foreign types owning no heap memory.
+#[proc_macro_derive(HeapSize, attributes(heap_size))]
+pub fn derive_heap_size(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    heap_size::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `redismod::Config` parsing `KEYWORD value` load arguments into the fields.
+///
+/// ```ignore
+/// #[derive(Clone, Config)]
+/// #[config(validate = "WorkerConfig::validate")]
+/// pub struct WorkerConfig {
+///     #[config(default = "5s", option)]
+///     pub timeout: Duration,
+///     #[config(name = "RETRIES", default)]
+///     pub max_retries: u64,
+///     pub queue: Option<String>,
+/// }
+/// ```
+///
+/// Keywords are the upper case field names, matched case insensitively, and values are parsed
+/// with `redismod::ConfigValue`. Fields without `default` are required unless optional.
+/// Fields marked `option` are also registered as module configs named after the field, e.g.
+/// `<module>.timeout`, and `immutable` ones cannot be changed after load. The config kind
+/// follows `ConfigValue::option`: integers and durations, in milliseconds, are numeric configs
+/// bounded by `min = ..` and `max = ..`, `bool`s are bool configs, `ConfigValue` enums are enum
+/// configs and anything else is a string config.
+#[proc_macro_derive(Config, attributes(config))]
+pub fn derive_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    config::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `redismod::ConfigValue` for an enum without fields.
+///
+/// Variants are written in kebab case, `RoundRobin` as `round-robin`, unless renamed with
+/// `#[config(name = "...")]`. Registered as a module config, the enum is an enum config.
+#[proc_macro_derive(ConfigValue, attributes(config))]
+pub fn derive_config_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    config::expand_value(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `redismod::Command` and `TryFrom<Vec<RedisString>>` for a request.
+///
+/// ```ignore
+/// #[derive(Command)]
+/// #[command(validate = "TaskScan::validate")]
+/// pub struct TaskScan {
+///     cursor: u64,
+///     #[command(keyword, default = "10")]
+///     count: usize,
+///     #[command(flag = "WITHTYPES")]
+///     with_types: bool,
+///     #[command(rest)]
+///     types: Vec<String>,
+/// }
+/// ```
+///
+/// Fields are positional by default and parsed with `FromStr`, `bytes` and `millis` read a
+/// `Vec<u8>` or a `Duration` instead. `keyword` fields are `Option`s, or set a default, read
+/// from `KEYWORD value` in any order after the positional ones. `flag` fields are `bool`s and
+/// a `rest` field collects every argument left. `USAGE` describes the arguments.
+///
+/// `validate` names a `fn(&Self) -> Result<(), RedisError>` run once the request is parsed.
+#[proc_macro_derive(Command, attributes(command))]
+pub fn derive_command(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    command::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
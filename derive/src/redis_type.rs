@@ -0,0 +1,332 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse_quote,
+    visit::{self, Visit},
+    Data,
+    DeriveInput,
+    Field,
+    Fields,
+    Ident,
+    LitInt,
+    LitStr,
+    Path,
+    Token,
+    Type,
+};
+
+use crate::heap_size;
+
+// redis requires data type names of exactly 9 chars from A-Z, a-z, 0-9, '-' and '_'
+const REDIS_NAME_LEN: usize = 9;
+
+// optional `Type` hooks forwarded to a user function
+const HOOKS: &[&str] = &[
+    "aof_rewrite",
+    "free_effort",
+    "unlink",
+    "digest",
+    "copy",
+    "defrag",
+    "indexes",
+    "default_ttl",
+];
+
+#[derive(Default)]
+struct TypeAttrs {
+    name: Option<LitStr>,
+    prefix: Option<LitStr>,
+    redis_name: Option<LitStr>,
+    version: Option<LitInt>,
+    id: Option<Ident>,
+    migrations: Option<Type>,
+    hooks: Vec<(Ident, Path)>,
+    copy_clone: bool,
+}
+
+impl TypeAttrs {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut attrs = Self::default();
+
+        for attr in input.attrs.iter() {
+            if !attr.path().is_ident("redis_type") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    attrs.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("prefix") {
+                    attrs.prefix = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("redis_name") {
+                    attrs.redis_name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("version") {
+                    attrs.version = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("id") {
+                    let field: LitStr = meta.value()?.parse()?;
+
+                    attrs.id = Some(field.parse()?);
+                } else if meta.path.is_ident("migrations") {
+                    let migrations: LitStr = meta.value()?.parse()?;
+
+                    attrs.migrations = Some(migrations.parse()?);
+                } else if meta.path.is_ident("copy") && !meta.input.peek(Token![=]) {
+                    attrs.copy_clone = true;
+                } else if let Some(hook) = HOOKS.iter().find(|hook| meta.path.is_ident(hook)) {
+                    let path: LitStr = meta.value()?.parse()?;
+
+                    attrs
+                        .hooks
+                        .push((Ident::new(hook, path.span()), path.parse()?));
+                } else {
+                    return Err(meta.error("unsupported redis_type attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(attrs)
+    }
+}
+
+/// Finds `HashMap` and `HashSet` types, their order differs between processes.
+#[derive(Default)]
+struct Unordered<'t> {
+    found: Option<&'t syn::TypePath>,
+}
+
+impl<'t> Visit<'t> for Unordered<'t> {
+    fn visit_type_path(&mut self, path: &'t syn::TypePath) {
+        let unordered = path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "HashMap" || segment.ident == "HashSet");
+
+        if unordered && self.found.is_none() {
+            self.found = Some(path);
+        }
+
+        visit::visit_type_path(self, path);
+    }
+}
+
+/// The default digest feeds the fields in serialization order, which must be the same on
+/// every server.
+fn check_digest_order<'f, I>(fields: I) -> syn::Result<()>
+where
+    I: IntoIterator<Item = &'f Field>,
+{
+    let mut unordered = Unordered::default();
+
+    for field in fields {
+        unordered.visit_type(&field.ty);
+    }
+
+    match unordered.found {
+        Some(path) => Err(syn::Error::new_spanned(
+            path,
+            "hash containers digest in a per-process order, use BTreeMap/BTreeSet or set a \
+             #[redis_type(digest = ...)] hook",
+        )),
+        None => Ok(()),
+    }
+}
+
+fn required<T>(value: Option<T>, input: &DeriveInput, attr: &str) -> syn::Result<T> {
+    value.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            format!("missing #[redis_type({} = ...)] attribute", attr),
+        )
+    })
+}
+
+fn validate_redis_name(redis_name: &LitStr) -> syn::Result<()> {
+    let value = redis_name.value();
+
+    let valid_chars = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if value.len() != REDIS_NAME_LEN || !valid_chars {
+        return Err(syn::Error::new_spanned(
+            redis_name,
+            "redis_name must be exactly 9 chars of A-Z, a-z, 0-9, '-' or '_'",
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let attrs = TypeAttrs::parse(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "RedisType can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "RedisType can only be derived for structs",
+            ))
+        }
+    };
+
+    let name = required(attrs.name, &input, "name")?;
+    let prefix = required(attrs.prefix, &input, "prefix")?;
+    let redis_name = required(attrs.redis_name, &input, "redis_name")?;
+    let version = required(attrs.version, &input, "version")?;
+    let id = required(attrs.id, &input, "id")?;
+    let migrations = attrs.migrations.unwrap_or_else(|| parse_quote!(()));
+
+    validate_redis_name(&redis_name)?;
+
+    if !attrs.hooks.iter().any(|(hook, _)| hook == "digest") {
+        check_digest_order(fields)?;
+    }
+
+    let id_type = fields
+        .iter()
+        .find(|field| field.ident.as_ref() == Some(&id))
+        .map(|field| &field.ty)
+        .ok_or_else(|| syn::Error::new_spanned(&id, "id field not found"))?;
+
+    let mut heap_fields = Vec::new();
+
+    for field in fields.iter() {
+        if let Some(attr) = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("redis_type"))
+        {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "unsupported redis_type field attribute, heap sizes are skipped with \
+                 #[heap_size(skip)]",
+            ));
+        }
+
+        if heap_size::skipped(field)? {
+            continue;
+        }
+
+        if let Some(ident) = &field.ident {
+            heap_fields.push(ident);
+        }
+    }
+
+    let hooks = attrs
+        .hooks
+        .iter()
+        .map(|(hook, path)| expand_hook(hook, path));
+
+    let copy_clone = attrs.copy_clone.then(|| {
+        quote! {
+            fn copy(value: &Self, _from_key: &[u8], _to_key: &[u8]) -> ::std::option::Option<Self> {
+                ::std::option::Option::Some(::std::clone::Clone::clone(value))
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::redismod::Type for #ident #ty_generics #where_clause {
+            type IDType = #id_type;
+            type Migrations = #migrations;
+
+            const NAME: &'static str = #name;
+            const PREFIX: &'static str = #prefix;
+
+            const REDIS_NAME: &'static str = #redis_name;
+            const REDIS_VERSION: i32 = #version;
+
+            fn free(_value: ::std::boxed::Box<Self>) {}
+
+            fn mem_usage(value: &Self) -> usize {
+                ::std::mem::size_of::<Self>()
+                    #( + ::redismod::HeapSize::heap_size(&value.#heap_fields) )*
+            }
+
+            fn rdb_save<S: ::redismod::Saver>(saver: &S, value: &Self) {
+                // the key is saved as a marker failing its load, the next keys stay readable
+                if let Err(err) = ::redismod::to_saver_marked(value, saver) {
+                    ::redismod::__log::error!(target: #name, "rdb save failed: {}", err);
+                }
+            }
+
+            fn rdb_load<L: ::redismod::Loader>(
+                loader: &L,
+                _encver: usize,
+            ) -> ::std::result::Result<Self, ::redismod::__rm::error::Error> {
+                ::std::result::Result::Ok(::redismod::from_loader_marked(loader)?)
+            }
+
+            #( #hooks )*
+
+            #copy_clone
+        }
+    })
+}
+
+fn expand_hook(hook: &Ident, path: &Path) -> TokenStream {
+    match hook.to_string().as_str() {
+        "aof_rewrite" => quote! {
+            const HAS_AOF_REWRITE: bool = true;
+
+            fn aof_rewrite(emitter: &::redismod::AofEmitter, key: &[u8], value: &Self) {
+                #path(emitter, key, value)
+            }
+        },
+        "free_effort" => quote! {
+            fn free_effort(key: &[u8], value: &Self) -> usize {
+                #path(key, value)
+            }
+        },
+        "unlink" => quote! {
+            fn unlink(key: &[u8], value: &Self) {
+                #path(key, value)
+            }
+        },
+        "digest" => quote! {
+            fn digest(digest: &::redismod::Digest, value: &Self) {
+                #path(digest, value)
+            }
+        },
+        "copy" => quote! {
+            fn copy(value: &Self, from_key: &[u8], to_key: &[u8]) -> ::std::option::Option<Self> {
+                #path(value, from_key, to_key)
+            }
+        },
+        "defrag" => quote! {
+            fn defrag(
+                ctx: &::redismod::DefragCtx,
+                key: &[u8],
+                value: &mut Self,
+            ) -> ::redismod::Defrag {
+                #path(ctx, key, value)
+            }
+        },
+        "indexes" => quote! {
+            fn indexes() -> ::std::vec::Vec<::redismod::Index<Self>> {
+                #path()
+            }
+        },
+        "default_ttl" => quote! {
+            fn default_ttl(value: &Self) -> ::std::option::Option<::std::time::Duration> {
+                #path(value)
+            }
+        },
+        _ => unreachable!("hook {} is listed in HOOKS", hook),
+    }
+}
@@ -1,11 +1,27 @@
-use redis_module::{RedisError, RedisString};
+use std::time::Duration;
 
-pub struct ExampleConfig;
+use redis_module::RedisError;
 
-impl TryFrom<Vec<RedisString>> for ExampleConfig {
-    type Error = RedisError;
+use redismod::Config;
 
-    fn try_from(_value: Vec<RedisString>) -> Result<Self, Self::Error> {
-        Ok(Self)
+#[derive(Clone, Config)]
+#[config(validate = "ExampleConfig::check_limits")]
+pub struct ExampleConfig {
+    /// Prefix of every key of the module, `NAMESPACE <ns>` load argument or `example.namespace`.
+    #[config(option, immutable)]
+    pub namespace: Option<String>,
+    /// Longest task timeout, `MAX_TIMEOUT <duration>` load argument or `example.max-timeout`
+    /// in milliseconds.
+    #[config(default = "1h", option, min = 1)]
+    pub max_timeout: Duration,
+}
+
+impl ExampleConfig {
+    fn check_limits(&self) -> Result<(), RedisError> {
+        if self.max_timeout.is_zero() {
+            return Err(RedisError::Str("MAX_TIMEOUT must be greater than zero"));
+        }
+
+        Ok(())
     }
 }
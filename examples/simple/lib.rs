@@ -1,21 +1,76 @@
 mod config;
+mod middleware;
 mod requests;
 mod types;
 
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
 use redis_module as rm;
 
-use redismod::{module, Module, ModuleStores, Store};
+use redismod::{
+    module,
+    Async,
+    AuxWhen,
+    Blocking,
+    IOLoader,
+    IOSaver,
+    IntoRedisError,
+    Jobs,
+    Loader,
+    Middleware,
+    Module,
+    ModuleStores,
+    Namespaced,
+    Saver,
+    Store,
+    Timers,
+};
 
-use types::Task;
-use requests::TaskCreate;
+use middleware::SlowLog;
+use types::{Task, TaskId, TaskState, TaskTimeout};
+use requests::{TaskCreate, TaskFind, TaskFinish, TaskPop, TaskRestore, TaskScan, TaskValidate};
 
-module![ExampleModule];
+module![ExampleModule, allocator];
 
 #[derive(Debug, thiserror::Error)]
-pub enum ExampleError {}
+pub enum ExampleError {
+    #[error("task {0} already exists")]
+    TaskExists(TaskId),
+    #[error("task {0} payload is not utf-8: {1}")]
+    InvalidPayload(TaskId, std::str::Utf8Error),
+    #[error(transparent)]
+    Store(#[from] redismod::Error),
+}
+
+impl IntoRedisError for ExampleError {
+    fn code(&self) -> &str {
+        match self {
+            Self::TaskExists(_) => "EXISTS",
+            Self::InvalidPayload(..) => "INVALID",
+            Self::Store(err) => err.code(),
+        }
+    }
+}
 
 struct ExampleModule {
     store_task: Store<Task>,
+    /// Number of tasks ever created, kept in the rdb aux section.
+    created: AtomicU64,
+    /// Fails tasks not finished before their timeout, persisted in the aux data.
+    timeouts: Timers<TaskTimeout>,
+    jobs: Jobs<ExampleModule>,
+    /// Longest task timeout in milliseconds, changed by `CONFIG SET example.max-timeout`.
+    max_timeout: AtomicU64,
+    slow_log: SlowLog,
+}
+
+impl ExampleModule {
+    fn max_timeout(&self) -> Duration {
+        Duration::from_millis(self.max_timeout.load(Ordering::Relaxed))
+    }
 }
 
 impl Module for ExampleModule {
@@ -24,8 +79,82 @@ impl Module for ExampleModule {
 
     type Error = ExampleError;
     type Config = config::ExampleConfig;
-    type Requests = (TaskCreate,);
+    type Requests = (
+        TaskCreate,
+        TaskRestore,
+        TaskScan,
+        TaskFind,
+        TaskFinish,
+        Async<TaskValidate>,
+        Blocking<TaskPop>,
+    );
     type DataTypes = (Task,);
+    type Timer = TaskTimeout;
+
+    const AUX_NAME: Option<&'static str> = Some("exmplaux1");
+    // 2: task timeouts after the created counter
+    const AUX_VERSION: i32 = 2;
+
+    fn aux_save(&self, saver: &IOSaver, _when: AuxWhen) {
+        saver.unsigned(self.created.load(Ordering::Relaxed));
+
+        if let Err(err) = self.timeouts.aux_save(saver) {
+            log::error!(target: "module", "cannot save task timeouts: {}", err);
+        }
+    }
+
+    fn aux_load(
+        &self,
+        loader: &IOLoader,
+        encver: usize,
+        _when: AuxWhen,
+    ) -> Result<(), rm::error::Error> {
+        self.created.store(loader.unsigned()?, Ordering::Relaxed);
+
+        if encver >= 2 {
+            self.timeouts.aux_load(loader)?;
+        }
+
+        Ok(())
+    }
+
+    fn timers(&self) -> Option<&Timers<TaskTimeout>> {
+        Some(&self.timeouts)
+    }
+
+    fn on_timer(&self, ctx: &rm::Context, TaskTimeout(id): TaskTimeout) {
+        let timed_out = self
+            .store_task
+            .get_mut(ctx, &id)
+            .update(|task| match task.state {
+                TaskState::Pending | TaskState::Started => {
+                    task.state = TaskState::Failed;
+                    true
+                }
+                TaskState::Failed | TaskState::Finished => false,
+            });
+
+        match timed_out {
+            Ok(true) => log::info!(target: "module", "task {} timed out", id),
+            Ok(false) => {}
+            Err(err) => log::warn!(target: "module", "task {} timeout: {}", id, err),
+        }
+    }
+
+    fn jobs(&self) -> Option<&Jobs<Self>> {
+        Some(&self.jobs)
+    }
+
+    fn middleware(&self) -> Option<&dyn Middleware<Self>> {
+        Some(&self.slow_log)
+    }
+
+    fn reconfigure(&self, _ctx: &rm::Context, config: &Self::Config) -> Result<(), Self::Error> {
+        self.max_timeout
+            .store(config.max_timeout.as_millis() as u64, Ordering::Relaxed);
+
+        Ok(())
+    }
 
     fn stop(&self, _ctx: &rm::Context) -> Result<(), Self::Error> {
         log::info!(target: "module", "stop");
@@ -35,17 +164,48 @@ impl Module for ExampleModule {
     fn start(&mut self, _ctx: &rm::Context) -> Result<(), Self::Error> {
         log::info!(target: "module", "start");
 
+        self.jobs
+            .every("task-stats", Duration::from_secs(60), |module, job| {
+                let ctx = match job.lock() {
+                    Some(ctx) => ctx,
+                    None => return,
+                };
+
+                for state in [TaskState::Pending, TaskState::Started] {
+                    let state = state.as_str();
+
+                    match module.store_task.find_by(&ctx, "state", state) {
+                        Ok(ids) => log::info!(target: "module", "{} tasks {}", ids.len(), state),
+                        Err(err) => log::warn!(target: "module", "task stats: {}", err),
+                    }
+                }
+            });
+
         Ok(())
     }
     fn create(
         _ctx: &rm::Context,
-        _config: Self::Config,
+        config: Self::Config,
         stores: ModuleStores<Self>,
     ) -> Result<Self, Self::Error> {
         log::info!(target: "module", "create");
 
-        let (store_task,) = stores;
+        let (mut store_task,) = stores;
+        let max_timeout = AtomicU64::new(config.max_timeout.as_millis() as u64);
+
+        if let Some(namespace) = config.namespace {
+            store_task = store_task.with_codec(Namespaced::new(namespace));
+        }
 
-        Ok(Self { store_task })
+        Ok(Self {
+            store_task,
+            created: AtomicU64::new(0),
+            timeouts: Timers::persistent(),
+            jobs: Jobs::new(),
+            max_timeout,
+            slow_log: SlowLog {
+                threshold: Duration::from_millis(10),
+            },
+        })
     }
 }
@@ -0,0 +1,32 @@
+use std::time::{Duration, Instant};
+
+use redis_module as rm;
+
+use redismod::Middleware;
+
+use crate::ExampleModule;
+
+/// Logs the requests slower than `threshold`.
+pub struct SlowLog {
+    pub threshold: Duration,
+}
+
+impl Middleware<ExampleModule> for SlowLog {
+    fn around(
+        &self,
+        _ctx: &rm::Context,
+        _module: &ExampleModule,
+        command: &str,
+        next: &mut dyn FnMut() -> rm::RedisResult,
+    ) -> rm::RedisResult {
+        let started = Instant::now();
+        let result = next();
+        let elapsed = started.elapsed();
+
+        if elapsed >= self.threshold {
+            log::warn!(target: "module", "{} took {:?}", command, elapsed);
+        }
+
+        result
+    }
+}
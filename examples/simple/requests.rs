@@ -1,39 +1,40 @@
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
 
 use redis_module as rm;
-use redis_module::NextArg as _;
 
-use redismod::{CommandKeys, NextArgExt, RequestHandler};
+use redismod::{
+    AsyncRequestHandler,
+    BlockingRequestHandler,
+    Command,
+    CommandKeys,
+    Job,
+    RequestHandler,
+};
 
-use crate::ExampleModule;
-use crate::types::{Task, TaskState};
+use crate::{ExampleError, ExampleModule};
+use crate::types::{Task, TaskId, TaskState, TaskTimeout};
 
-#[derive(Debug)]
+#[derive(Debug, Command)]
+#[command(validate = "TaskCreate::check")]
 pub struct TaskCreate {
-    id: xid::Id,
+    id: TaskId,
     r#type: String,
     retries: u64,
+    #[command(millis)]
     timeout: Duration,
     worker: String,
+    #[command(bytes)]
     payload: Vec<u8>,
 }
 
-impl TryFrom<Vec<rm::RedisString>> for TaskCreate {
-    type Error = rm::RedisError;
-
-    fn try_from(value: Vec<rm::RedisString>) -> Result<Self, Self::Error> {
-        let mut args = value.into_iter().skip(1);
-
-        let id = args.next_parse::<xid::Id>()?;
+impl TaskCreate {
+    fn check(&self) -> Result<(), rm::RedisError> {
+        if self.worker.is_empty() {
+            return Err(rm::RedisError::Str("ERR worker is empty"));
+        }
 
-        Ok(Self {
-            id,
-            r#type: args.next_string()?,
-            retries: args.next_u64()?,
-            timeout: Duration::from_millis(args.next_u64()?),
-            worker: args.next_string()?,
-            payload: args.next_vec()?,
-        })
+        Ok(())
     }
 }
 
@@ -46,25 +47,258 @@ impl RequestHandler<TaskCreate> for ExampleModule {
         step: 1,
     };
 
-    type Result = rm::RedisResult;
+    type Result = Result<rm::RedisValue, ExampleError>;
 
     fn handle(&self, ctx: &rm::Context, req: TaskCreate) -> Self::Result {
-        if self.store_task.exists(ctx, &req.id)? {
-            return Err(rm::RedisError::Str("task already exists"));
+        let entry = self.store_task.get_mut(ctx, &req.id);
+
+        if entry.exists()? {
+            return Err(ExampleError::TaskExists(req.id));
         }
 
+        let timeout = req.timeout.min(self.max_timeout());
+
         let value = Task {
             id: req.id,
             r#type: req.r#type,
             retries: req.retries,
-            timeout: req.timeout,
+            timeout,
             worker: req.worker,
             payload: req.payload,
             state: TaskState::Pending,
+            created_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as u64),
         };
 
-        self.store_task.get_mut(ctx, &value.id).store(value)?;
+        entry.store(value)?;
+        self.created.fetch_add(1, Ordering::Relaxed);
+        self.timeouts.start(ctx, timeout, TaskTimeout(req.id));
+
+        Ok(rm::RedisValue::SimpleStringStatic("OK"))
+    }
+}
+
+#[derive(Debug, Command)]
+pub struct TaskRestore {
+    id: TaskId,
+    r#type: String,
+    retries: u64,
+    #[command(millis)]
+    timeout: Duration,
+    worker: String,
+    #[command(bytes)]
+    payload: Vec<u8>,
+    state: TaskState,
+    created_at: u64,
+}
+
+impl RequestHandler<TaskRestore> for ExampleModule {
+    const NAME: &'static str = "task_restore";
+    const FLAGS: &'static str = "write";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 1,
+        last: 1,
+        step: 1,
+    };
+
+    type Result = Result<rm::RedisValue, ExampleError>;
+
+    /// Stores a task written by `Task::aof_rewrite` as is: no timer, no counter and no expire,
+    /// the one of the key follows in the AOF.
+    fn handle(&self, ctx: &rm::Context, req: TaskRestore) -> Self::Result {
+        let entry = self.store_task.get_mut(ctx, &req.id);
+
+        let value = Task {
+            id: req.id,
+            r#type: req.r#type,
+            retries: req.retries,
+            timeout: req.timeout,
+            worker: req.worker,
+            payload: req.payload,
+            state: req.state,
+            created_at: req.created_at,
+        };
+
+        entry.store(value)?;
+
+        Ok(rm::RedisValue::SimpleStringStatic("OK"))
+    }
+}
+
+#[derive(Debug, Command)]
+pub struct TaskScan {
+    cursor: u64,
+    #[command(keyword, default = "10")]
+    count: usize,
+}
+
+impl RequestHandler<TaskScan> for ExampleModule {
+    const NAME: &'static str = "task_scan";
+    const FLAGS: &'static str = "readonly";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 0,
+        last: 0,
+        step: 0,
+    };
+
+    type Result = Result<rm::RedisValue, redismod::Error>;
+
+    /// `task_scan <cursor> [COUNT <count>]`, replies with the next cursor and task ids.
+    fn handle(&self, ctx: &rm::Context, req: TaskScan) -> Self::Result {
+        let page = self.store_task.scan(ctx, req.cursor, req.count)?;
+
+        Ok(page.into_reply(|id| rm::RedisValue::BulkString(id.to_string())))
+    }
+}
+
+#[derive(Debug, Command)]
+pub struct TaskFind {
+    index: String,
+    value: String,
+}
+
+impl RequestHandler<TaskFind> for ExampleModule {
+    const NAME: &'static str = "task_find";
+    const FLAGS: &'static str = "readonly";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 0,
+        last: 0,
+        step: 0,
+    };
+
+    type Result = Result<rm::RedisValue, redismod::Error>;
+
+    /// `task_find <state|worker> <value>`, replies with the ids of the matching tasks.
+    fn handle(&self, ctx: &rm::Context, req: TaskFind) -> Self::Result {
+        let ids = self.store_task.find_by(ctx, &req.index, &req.value)?;
+
+        Ok(rm::RedisValue::Array(
+            ids.into_iter()
+                .map(|id| rm::RedisValue::BulkString(id.to_string()))
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug, Command)]
+pub struct TaskFinish {
+    id: TaskId,
+}
+
+impl RequestHandler<TaskFinish> for ExampleModule {
+    const NAME: &'static str = "task_finish";
+    const FLAGS: &'static str = "fast write";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 1,
+        last: 1,
+        step: 1,
+    };
+
+    type Result = Result<rm::RedisValue, ExampleError>;
+
+    fn handle(&self, ctx: &rm::Context, req: TaskFinish) -> Self::Result {
+        self.store_task
+            .get_mut(ctx, &req.id)
+            .update(|task| task.state = TaskState::Finished)?;
 
         Ok(rm::RedisValue::SimpleStringStatic("OK"))
     }
 }
+
+#[derive(Debug, Command)]
+pub struct TaskValidate {
+    id: TaskId,
+}
+
+impl AsyncRequestHandler<TaskValidate> for ExampleModule {
+    const NAME: &'static str = "task_validate";
+    const FLAGS: &'static str = "readonly";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 1,
+        last: 1,
+        step: 1,
+    };
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(1));
+
+    type Result = Result<rm::RedisValue, ExampleError>;
+
+    /// `task_validate <id>`, checks the payload is utf-8 off the main thread.
+    fn handle(&self, ctx: &rm::Context, req: TaskValidate) -> Job<Self::Result> {
+        let payload = self
+            .store_task
+            .get(ctx, &req.id)
+            .load()
+            .map(|task| task.payload.clone());
+
+        Box::new(move |_| {
+            let payload = payload?;
+
+            match std::str::from_utf8(&payload) {
+                Ok(_) => Ok(rm::RedisValue::SimpleStringStatic("OK")),
+                Err(err) => Err(ExampleError::InvalidPayload(req.id, err)),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Command)]
+pub struct TaskPop {
+    worker: String,
+    #[command(keyword, millis)]
+    block: Option<Duration>,
+}
+
+impl BlockingRequestHandler<TaskPop> for ExampleModule {
+    const NAME: &'static str = "task_pop";
+    const FLAGS: &'static str = "write";
+    const KEYS: CommandKeys = CommandKeys {
+        first: 0,
+        last: 0,
+        step: 0,
+    };
+
+    type Result = Result<rm::RedisValue, ExampleError>;
+
+    /// `task_pop <worker> [BLOCK <ms>]`, starts a pending task for `worker` and replies with its
+    /// id and type, waits for one with `BLOCK`.
+    fn try_handle(&self, ctx: &rm::Context, req: &TaskPop) -> Option<Self::Result> {
+        let pending = match self
+            .store_task
+            .find_by(ctx, "state", TaskState::Pending.as_str())
+        {
+            Ok(pending) => pending,
+            Err(err) => return Some(Err(err.into())),
+        };
+
+        let id = match pending.into_iter().next() {
+            Some(id) => id,
+            None if req.block.is_some() => return None,
+            None => return Some(Ok(rm::RedisValue::Null)),
+        };
+
+        let started = self.store_task.get_mut(ctx, &id).update(|task| {
+            task.state = TaskState::Started;
+            task.worker = req.worker.clone();
+
+            rm::RedisValue::Array(vec![
+                rm::RedisValue::BulkString(task.id.to_string()),
+                rm::RedisValue::BulkString(task.r#type.clone()),
+            ])
+        });
+
+        Some(started.map_err(ExampleError::from))
+    }
+
+    fn keys(&self, _req: &TaskPop) -> Vec<Vec<u8>> {
+        self.store_task
+            .tag_key("state", TaskState::Pending.as_str())
+            .into_iter()
+            .map(String::into_bytes)
+            .collect()
+    }
+
+    fn timeout(&self, req: &TaskPop) -> Option<Duration> {
+        req.block.filter(|block| !block.is_zero())
+    }
+}
@@ -1,10 +1,51 @@
-use std::time::Duration;
+use std::{fmt, str::FromStr, time::Duration};
 
 use redis_module as rm;
+use serde::{Deserialize, Serialize};
+
+use redismod::{
+    AofEmitter,
+    Defrag,
+    DefragCtx,
+    HeapSize,
+    Index,
+    Initial,
+    KeyId,
+    Loader,
+    RedisType,
+    Schema,
+    Upgrade,
+};
+
+/// Id of a task, written into its key and the indexes in its text form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskId(#[serde(with = "id")] pub xid::Id);
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for TaskId {
+    type Err = <xid::Id as FromStr>::Err;
 
-use redismod::{IOLoader, IOSaver, Loader, Saver, Type};
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        id.parse().map(Self)
+    }
+}
 
-#[derive(Debug, Clone)]
+impl KeyId for TaskId {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_string().into_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        std::str::from_utf8(bytes).ok()?.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, HeapSize)]
 pub enum TaskState {
     Failed,
     Pending,
@@ -12,102 +53,169 @@ pub enum TaskState {
     Finished,
 }
 
-impl From<TaskState> for u64 {
-    fn from(value: TaskState) -> Self {
-        match value {
-            TaskState::Failed => 0,
-            TaskState::Pending => 1,
-            TaskState::Started => 2,
-            TaskState::Finished => 3,
+impl TaskState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Failed => "failed",
+            Self::Pending => "pending",
+            Self::Started => "started",
+            Self::Finished => "finished",
         }
     }
 }
 
-impl TryFrom<u64> for TaskState {
-    type Error = rm::RedisError;
+impl FromStr for TaskState {
+    type Err = String;
 
-    fn try_from(value: u64) -> Result<Self, Self::Error> {
-        Ok(match value {
-            0 => Self::Failed,
-            1 => Self::Pending,
-            2 => Self::Started,
-            3 => Self::Finished,
-            _ => return Err(rm::RedisError::Str("invalid task state")),
-        })
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "failed" => Ok(Self::Failed),
+            "pending" => Ok(Self::Pending),
+            "started" => Ok(Self::Started),
+            "finished" => Ok(Self::Finished),
+            _ => Err(format!("unknown task state {}", state)),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, RedisType)]
+#[redis_type(
+    name = "task",
+    prefix = "t",
+    redis_name = "taskver10",
+    version = 2,
+    id = "id",
+    migrations = "redismod::MigrateFrom<TaskV1>",
+    aof_rewrite = "Task::aof_rewrite",
+    defrag = "Task::defrag",
+    indexes = "Task::indexes",
+    copy
+)]
 pub struct Task {
-    pub id: xid::Id,
+    #[heap_size(skip)]
+    pub id: TaskId,
     pub r#type: String,
     pub retries: u64,
+    #[serde(with = "millis")]
     pub timeout: Duration,
     pub worker: String,
+    #[serde(with = "serde_bytes")]
     pub payload: Vec<u8>,
     pub state: TaskState,
+    /// Unix time in milliseconds, `0` for tasks created before version 2.
+    pub created_at: u64,
 }
 
-impl Type for Task {
-    type IDType = xid::Id;
-
-    const NAME: &'static str = "task";
-    const PREFIX: &'static str = "t";
-
-    const REDIS_NAME: &'static str = "taskver10";
-    const REDIS_VERSION: i32 = 1;
+impl Task {
+    /// Rebuilds the task as is with `task_restore`, redis appends the expire.
+    fn aof_rewrite(emitter: &AofEmitter, _key: &[u8], task: &Task) {
+        emitter.emit(
+            "example.task_restore",
+            &[
+                task.id.to_string().as_bytes(),
+                task.r#type.as_bytes(),
+                task.retries.to_string().as_bytes(),
+                task.timeout.as_millis().to_string().as_bytes(),
+                task.worker.as_bytes(),
+                task.payload.as_slice(),
+                task.state.as_str().as_bytes(),
+                task.created_at.to_string().as_bytes(),
+            ],
+        );
+    }
 
-    fn free(_value: Box<Self>) {}
+    fn defrag(ctx: &DefragCtx, _key: &[u8], task: &mut Task) -> Defrag {
+        ctx.defrag_string(&mut task.r#type);
+        ctx.defrag_string(&mut task.worker);
+        ctx.defrag_vec(&mut task.payload);
 
-    fn mem_usage(_value: &Self) -> usize {
-        0
+        Defrag::Done
     }
 
-    fn rdb_save(saver: &IOSaver, value: &Self) {
-        saver.buffer(value.id.as_bytes());
-        saver.buffer(value.r#type.as_bytes());
-        saver.unsigned(value.retries);
-        saver.unsigned(value.timeout.as_millis().try_into().unwrap_or(5_000));
-        saver.buffer(value.worker.as_bytes());
-        saver.buffer(value.payload.as_slice());
-        saver.unsigned(value.state.clone().into());
+    fn indexes() -> Vec<Index<Task>> {
+        vec![
+            Index::tag("state", |task: &Task| task.state.as_str().to_string()),
+            Index::tag("worker", |task: &Task| task.worker.clone()),
+            Index::numeric("created_at", |task: &Task| task.created_at as f64),
+        ]
     }
+}
 
-    fn rdb_load(loader: &IOLoader, _encver: usize) -> Result<Self, rm::error::Error> {
-        let id = {
-            let bytes: [u8; 12] = loader
-                .buffer()?
-                .as_ref()
-                .try_into()
-                .map_err(|_| rm::error::Error::generic("id len missmatch"))?;
+/// Payload of the timer failing a task after its timeout.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskTimeout(pub TaskId);
 
-            xid::Id(bytes)
-        };
+/// Task layout persisted with encver 1.
+#[derive(Deserialize)]
+pub struct TaskV1 {
+    pub id: TaskId,
+    pub r#type: String,
+    pub retries: u64,
+    #[serde(with = "millis")]
+    pub timeout: Duration,
+    pub worker: String,
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+    pub state: TaskState,
+}
 
-        let r#type = loader
-            .buffer()?
-            .to_string()
-            .map_err(rm::error::Error::FromUtf8)?;
+impl Schema for TaskV1 {
+    const VERSION: usize = 1;
 
-        let retries = loader.unsigned()?;
+    type Prev = Initial;
 
-        let timeout = Duration::from_millis(loader.unsigned()?);
-        let worker = loader
-            .buffer()?
-            .to_string()
-            .map_err(rm::error::Error::FromUtf8)?;
+    fn rdb_load<L: Loader>(loader: &L) -> Result<Self, rm::error::Error> {
+        Ok(redismod::from_loader(loader)?)
+    }
 
-        let payload = loader.buffer()?.as_ref().to_vec();
-        let state = loader.unsigned()?.try_into()?;
+    fn upgrade(prev: Self::Prev) -> Result<Self, rm::error::Error> {
+        match prev {}
+    }
+}
 
+impl Upgrade<TaskV1> for Task {
+    fn upgrade(prev: TaskV1) -> Result<Self, rm::error::Error> {
         Ok(Self {
-            id,
-            r#type,
-            retries,
-            timeout,
-            worker,
-            payload,
-            state,
+            id: prev.id,
+            r#type: prev.r#type,
+            retries: prev.retries,
+            timeout: prev.timeout,
+            worker: prev.worker,
+            payload: prev.payload,
+            state: prev.state,
+            created_at: 0,
         })
     }
 }
+
+mod id {
+    use serde::{de::Error as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &xid::Id, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(id.as_bytes())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<xid::Id, D::Error> {
+        let bytes: Vec<u8> = serde_bytes::deserialize(deserializer)?;
+
+        let bytes: [u8; 12] = bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("id len missmatch"))?;
+
+        Ok(xid::Id(bytes))
+    }
+}
+
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis().try_into().unwrap_or(5_000))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
@@ -1,4 +1,4 @@
-use std::{str, time};
+use std::{iter, str, time, vec};
 use std::fmt::Debug;
 
 use redis_module as rm;
@@ -36,3 +36,130 @@ pub trait NextArgExt: rm::NextArg {
         Ok(s.as_slice().to_vec())
     }
 }
+
+/// Arguments of a request read by a `#[derive(Command)]` parser, without the command name.
+///
+/// Missing arguments are `WrongArity` errors, invalid ones are named in the error.
+pub struct CommandArgs {
+    args: iter::Peekable<vec::IntoIter<rm::RedisString>>,
+}
+
+impl CommandArgs {
+    pub fn new(args: Vec<rm::RedisString>) -> Self {
+        let mut args = args.into_iter().peekable();
+
+        args.next();
+
+        Self { args }
+    }
+
+    pub fn parse<T>(&mut self, name: &str) -> Result<T, rm::RedisError>
+    where
+        T: str::FromStr,
+        T::Err: Debug,
+    {
+        self.args.next_parse().map_err(|err| named(name, err))
+    }
+
+    pub fn bytes(&mut self, name: &str) -> Result<Vec<u8>, rm::RedisError> {
+        self.args.next_vec().map_err(|err| named(name, err))
+    }
+
+    pub fn millis(&mut self, name: &str) -> Result<time::Duration, rm::RedisError> {
+        self.args.next_millis().map_err(|err| named(name, err))
+    }
+
+    /// Takes the next argument if it is `keyword`, case insensitively.
+    pub fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self
+            .args
+            .peek()
+            .is_some_and(|arg| arg.as_slice().eq_ignore_ascii_case(keyword.as_bytes()));
+
+        if found {
+            self.args.next();
+        }
+
+        found
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.args.peek().is_none()
+    }
+
+    /// Fails on the first argument left.
+    pub fn finish(mut self) -> Result<(), rm::RedisError> {
+        match self.args.next() {
+            Some(arg) => Err(rm::RedisError::String(format!(
+                "ERR unexpected argument {}",
+                String::from_utf8_lossy(arg.as_slice())
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn named(name: &str, err: rm::RedisError) -> rm::RedisError {
+    match err {
+        rm::RedisError::WrongArity => rm::RedisError::WrongArity,
+        err => rm::RedisError::String(format!("ERR invalid {}: {}", name, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use redis_module as rm;
+
+    use crate::Command;
+
+    #[allow(dead_code)]
+    #[derive(Command)]
+    #[command(validate = "Scan::check")]
+    struct Scan {
+        cursor: u64,
+        #[command(millis)]
+        timeout: Duration,
+        #[command(keyword, default = "10")]
+        count: usize,
+        #[command(keyword = "MATCH")]
+        pattern: Option<String>,
+        #[command(flag = "WITHTYPES")]
+        with_types: bool,
+        #[command(rest)]
+        types: Vec<String>,
+    }
+
+    impl Scan {
+        fn check(&self) -> Result<(), rm::RedisError> {
+            match self.count {
+                0 => Err(rm::RedisError::Str("ERR count is zero")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn derive() {
+        assert_eq!(
+            Scan::USAGE,
+            "<cursor> <timeout> [COUNT <count>] [MATCH <pattern>] [WITHTYPES] [<types> ...]"
+        );
+
+        let mut scan = Scan {
+            cursor: 0,
+            timeout: Duration::from_secs(1),
+            count: 10,
+            pattern: None,
+            with_types: false,
+            types: Vec::new(),
+        };
+
+        assert!(scan.validate().is_ok());
+
+        scan.count = 0;
+
+        assert!(scan.validate().is_err());
+    }
+}
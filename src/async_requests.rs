@@ -0,0 +1,247 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use once_cell::sync::{Lazy, OnceCell};
+use redis_module as rm;
+
+use crate::{guard, Command, CommandKeys, IntoReply, Module, RequestHandler};
+
+/// Cancel flags of the clients blocked by running jobs, by blocked client.
+static CANCELS: Lazy<Mutex<HashMap<usize, Cancelled>>> = Lazy::new(Default::default);
+
+/// Workers of the module, sized by `Module::ASYNC_WORKERS` and `Module::ASYNC_QUEUE`.
+static POOL: OnceCell<SyncSender<Task>> = OnceCell::new();
+
+/// Work of an [`AsyncRequestHandler`], run on a worker thread without the redis lock.
+pub type Job<T> = Box<dyn FnOnce(&Cancelled) -> T + Send>;
+
+/// Set once the client timed out or disconnected, the reply of the job is dropped then.
+#[derive(Clone, Default)]
+pub struct Cancelled(Arc<AtomicBool>);
+
+impl Cancelled {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+}
+
+/// Command blocking the client while a [`Job`] runs off the main thread.
+///
+/// Listed in `Module::Requests` as `Async<R>`. Jobs share `Module::ASYNC_WORKERS` threads, a
+/// request finding `Module::ASYNC_QUEUE` jobs waiting is replied a `BUSY` error.
+pub trait AsyncRequestHandler<R: Command> {
+    const NAME: &'static str;
+    const FLAGS: &'static str;
+    const KEYS: CommandKeys;
+    /// The client gets a `-TIMEOUT` error after it, `None` waits for the job.
+    const TIMEOUT: Option<Duration> = None;
+
+    type Result: IntoReply + Send + 'static;
+
+    /// Runs on the main thread, keys have to be read here and moved into the job.
+    fn handle(&self, ctx: &rm::Context, req: R) -> Job<Self::Result>;
+}
+
+/// Request `R` handled by an [`AsyncRequestHandler`].
+pub struct Async<R>(pub R);
+
+impl<R: Command> TryFrom<Vec<rm::RedisString>> for Async<R> {
+    type Error = rm::RedisError;
+
+    fn try_from(value: Vec<rm::RedisString>) -> Result<Self, Self::Error> {
+        R::try_from(value).map(Async)
+    }
+}
+
+impl<R: Command> Command for Async<R> {
+    fn validate(&self) -> Result<(), rm::RedisError> {
+        self.0.validate()
+    }
+
+    fn request(&self) -> &dyn Any {
+        self.0.request()
+    }
+}
+
+impl<M, R> RequestHandler<Async<R>> for M
+where
+    M: AsyncRequestHandler<R> + Module,
+    R: Command,
+{
+    const NAME: &'static str = <M as AsyncRequestHandler<R>>::NAME;
+    const FLAGS: &'static str = <M as AsyncRequestHandler<R>>::FLAGS;
+    const KEYS: CommandKeys = <M as AsyncRequestHandler<R>>::KEYS;
+
+    type Result = rm::RedisResult;
+
+    fn handle(&self, ctx: &rm::Context, req: Async<R>) -> Self::Result {
+        let job = AsyncRequestHandler::handle(self, ctx, req.0);
+
+        spawn::<M, _>(
+            ctx,
+            <M as AsyncRequestHandler<R>>::NAME,
+            <M as AsyncRequestHandler<R>>::TIMEOUT,
+            job,
+        );
+
+        Ok(rm::RedisValue::NoReply)
+    }
+}
+
+struct BlockedClient(*mut rm::RedisModuleBlockedClient);
+
+unsafe impl Send for BlockedClient {}
+
+/// Job of a blocked client, queued for a worker.
+struct Task {
+    command: &'static str,
+    client: BlockedClient,
+    cancelled: Cancelled,
+    run: Box<dyn FnOnce(&Cancelled) -> rm::RedisResult + Send>,
+}
+
+fn spawn<M, T>(ctx: &rm::Context, command: &'static str, timeout: Option<Duration>, job: Job<T>)
+where
+    M: Module,
+    T: IntoReply + Send + 'static,
+{
+    let timeout_ms = timeout.map_or(0, |timeout| timeout.as_millis().max(1) as i64);
+
+    let client = unsafe {
+        rm::raw::RedisModule_BlockClient.unwrap()(ctx.ctx, None, Some(on_timeout), None, timeout_ms)
+    };
+
+    let cancelled = Cancelled::default();
+
+    CANCELS
+        .lock()
+        .unwrap()
+        .insert(client as usize, cancelled.clone());
+
+    unsafe { rm::raw::RedisModule_SetDisconnectCallback.unwrap()(client, Some(on_disconnect)) };
+
+    let task = Task {
+        command,
+        client: BlockedClient(client),
+        cancelled,
+        run: Box::new(move |cancelled| job(cancelled).into_reply()),
+    };
+
+    let pool = POOL.get_or_init(|| start_workers(M::ASYNC_WORKERS, M::ASYNC_QUEUE));
+
+    match pool.try_send(task) {
+        Ok(()) => {}
+        Err(TrySendError::Full(task)) => finish(
+            task.client,
+            &task.cancelled,
+            Err(rm::RedisError::Str("BUSY too many requests")),
+        ),
+        Err(TrySendError::Disconnected(task)) => finish(
+            task.client,
+            &task.cancelled,
+            Err(rm::RedisError::Str("ERR no workers")),
+        ),
+    }
+}
+
+/// Starts `workers` threads taking jobs from a queue of `queue` jobs.
+fn start_workers(workers: usize, queue: usize) -> SyncSender<Task> {
+    let (sender, receiver) = mpsc::sync_channel(queue);
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for i in 0..workers.max(1) {
+        let receiver = receiver.clone();
+
+        let spawned = thread::Builder::new()
+            .name(format!("async-{}", i))
+            .spawn(move || work(&receiver));
+
+        if let Err(err) = spawned {
+            log::error!(target: "async", "cannot spawn worker {}: {}", i, err);
+        }
+    }
+
+    sender
+}
+
+fn work(receiver: &Mutex<Receiver<Task>>) {
+    loop {
+        // the lock is released once a task is received, the other workers wait for the next one
+        let task = match receiver.lock().unwrap().recv() {
+            Ok(task) => task,
+            Err(_) => return,
+        };
+
+        let Task {
+            command,
+            client,
+            cancelled,
+            run,
+        } = task;
+
+        // a panicked job still replies and unblocks the client
+        let result = match guard::catch(command, || run(&cancelled)) {
+            Some(result) => result,
+            None => guard::panicked(command),
+        };
+
+        finish(client, &cancelled, result);
+    }
+}
+
+/// Replies `result`, unless the client is gone or timed out, and unblocks it.
+fn finish(client: BlockedClient, cancelled: &Cancelled, result: rm::RedisResult) {
+    CANCELS.lock().unwrap().remove(&(client.0 as usize));
+
+    unsafe {
+        if !cancelled.is_cancelled() {
+            // replies of a thread safe context are sent once the client is unblocked
+            let reply_ctx = rm::raw::RedisModule_GetThreadSafeContext.unwrap()(client.0);
+
+            rm::Context::new(reply_ctx).reply(result);
+
+            rm::raw::RedisModule_FreeThreadSafeContext.unwrap()(reply_ctx);
+        }
+
+        rm::raw::RedisModule_UnblockClient.unwrap()(client.0, ptr::null_mut());
+    }
+}
+
+fn cancel(client: *mut rm::RedisModuleBlockedClient) {
+    if let Some(cancelled) = CANCELS.lock().unwrap().get(&(client as usize)) {
+        cancelled.cancel();
+    }
+}
+
+unsafe extern "C" fn on_timeout(
+    ctx: *mut rm::RedisModuleCtx,
+    _argv: *mut *mut rm::RedisModuleString,
+    _argc: ffi::c_int,
+) -> ffi::c_int {
+    cancel(rm::raw::RedisModule_GetBlockedClientHandle.unwrap()(ctx));
+
+    rm::Context::new(ctx).reply_error_string("TIMEOUT request timed out") as ffi::c_int
+}
+
+unsafe extern "C" fn on_disconnect(
+    _ctx: *mut rm::RedisModuleCtx,
+    client: *mut rm::RedisModuleBlockedClient,
+) {
+    cancel(client);
+}
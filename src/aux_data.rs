@@ -0,0 +1,134 @@
+use std::{ffi, ptr};
+
+use redis_module as rm;
+
+use crate::{guard, IOLoader, IOSaver, InstanceMngr, Module};
+
+/// Point of the rdb where module aux data is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxWhen {
+    BeforeKeyspace,
+    AfterKeyspace,
+}
+
+impl AuxWhen {
+    fn as_flag(self) -> ffi::c_int {
+        let flag = match self {
+            Self::BeforeKeyspace => rm::raw::REDISMODULE_AUX_BEFORE_RDB,
+            Self::AfterKeyspace => rm::raw::REDISMODULE_AUX_AFTER_RDB,
+        };
+
+        flag as ffi::c_int
+    }
+
+    fn from_flag(flag: ffi::c_int) -> Self {
+        if flag == Self::BeforeKeyspace.as_flag() {
+            Self::BeforeKeyspace
+        } else {
+            Self::AfterKeyspace
+        }
+    }
+}
+
+/// Registers the data type carrying module aux data, if the module has any.
+///
+/// No key ever holds a value of this type, it only exists for its aux callbacks.
+pub(crate) fn register<M, G>(ctx: &rm::Context) -> Result<(), String>
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let name = match M::AUX_NAME {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let aux_save_triggers = M::AUX_TRIGGERS
+        .iter()
+        .fold(0, |triggers, when| triggers | when.as_flag());
+
+    let type_methods = rm::RedisModuleTypeMethods {
+        version: rm::REDISMODULE_TYPE_METHOD_VERSION.into(),
+        free: Some(free),
+        rdb_load: Some(rdb_load),
+        rdb_save: Some(rdb_save),
+        aof_rewrite: None,
+        mem_usage: None,
+        digest: None,
+        aux_load: Some(aux_load::<M, G>),
+        aux_save: Some(aux_save::<M, G>),
+        aux_save_triggers,
+        free_effort: None,
+        unlink: None,
+        copy: None,
+        defrag: None,
+
+        copy2: None,
+        unlink2: None,
+        free_effort2: None,
+        mem_usage2: None,
+    };
+
+    let aux_type = rm::native_types::RedisType::new(name, M::AUX_VERSION, type_methods);
+
+    aux_type.create_data_type(ctx.ctx).map_err(str::to_string)
+}
+
+unsafe extern "C" fn free(_value: *mut ffi::c_void) {}
+
+unsafe extern "C" fn rdb_save(_rdb: *mut rm::RedisModuleIO, _value: *mut ffi::c_void) {}
+
+unsafe extern "C" fn rdb_load(
+    _rdb: *mut rm::RedisModuleIO,
+    _encver: ffi::c_int,
+) -> *mut ffi::c_void {
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn aux_save<M, G>(rdb: *mut rm::RedisModuleIO, when: ffi::c_int)
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let instance = match G::get() {
+        Some(instance) => instance,
+        None => return,
+    };
+
+    let saver = IOSaver { rdb };
+
+    guard::catch("aux save", || {
+        instance.aux_save(&saver, AuxWhen::from_flag(when))
+    });
+}
+
+unsafe extern "C" fn aux_load<M, G>(
+    rdb: *mut rm::RedisModuleIO,
+    encver: ffi::c_int,
+    when: ffi::c_int,
+) -> ffi::c_int
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let instance = match G::get() {
+        Some(instance) => instance,
+        None => return rm::Status::Err as ffi::c_int,
+    };
+
+    let loader = IOLoader { rdb };
+
+    let loaded = guard::catch("aux load", || {
+        instance.aux_load(&loader, encver as usize, AuxWhen::from_flag(when))
+    });
+
+    match loaded {
+        Some(Ok(_)) => rm::Status::Ok as ffi::c_int,
+        Some(Err(err)) => {
+            log::error!(target: "aux", "aux load failed: {}", err);
+
+            rm::Status::Err as ffi::c_int
+        }
+        None => rm::Status::Err as ffi::c_int,
+    }
+}
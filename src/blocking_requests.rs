@@ -0,0 +1,182 @@
+use std::{any::Any, ffi, time::Duration};
+
+use redis_module as rm;
+
+use crate::{guard, store::create_string, Command, CommandKeys, IntoReply, RequestHandler};
+
+/// Command waiting for keys to be signaled, like `BLPOP`.
+///
+/// Listed in `Module::Requests` as `Blocking<R>`. Keys are signaled by `EntryMut::store` and
+/// `EntryMut::update`, for the entry key and the index keys the entry joins.
+pub trait BlockingRequestHandler<R: Command> {
+    const NAME: &'static str;
+    const FLAGS: &'static str;
+    const KEYS: CommandKeys;
+
+    type Result: IntoReply;
+
+    /// Serves the request, `None` blocks the client until one of `keys` is signaled.
+    ///
+    /// Called again with the same request each time a key is signaled.
+    fn try_handle(&self, ctx: &rm::Context, req: &R) -> Option<Self::Result>;
+
+    /// Keys the client waits on, binary safe like the ones of `Store::key`.
+    fn keys(&self, req: &R) -> Vec<Vec<u8>>;
+
+    /// Time the client waits, `None` waits until a key is signaled.
+    fn timeout(&self, req: &R) -> Option<Duration>;
+
+    /// Reply once the timeout is reached, or right away inside `MULTI` and scripts.
+    fn on_timeout(&self, _ctx: &rm::Context, _req: &R) -> rm::RedisResult {
+        Ok(rm::RedisValue::Null)
+    }
+}
+
+/// Request `R` handled by a [`BlockingRequestHandler`].
+pub struct Blocking<R>(pub R);
+
+impl<R: Command> TryFrom<Vec<rm::RedisString>> for Blocking<R> {
+    type Error = rm::RedisError;
+
+    fn try_from(value: Vec<rm::RedisString>) -> Result<Self, Self::Error> {
+        R::try_from(value).map(Blocking)
+    }
+}
+
+impl<R: Command> Command for Blocking<R> {
+    fn validate(&self) -> Result<(), rm::RedisError> {
+        self.0.validate()
+    }
+
+    fn request(&self) -> &dyn Any {
+        self.0.request()
+    }
+}
+
+impl<M, R> RequestHandler<Blocking<R>> for M
+where
+    M: BlockingRequestHandler<R> + 'static,
+    R: Command,
+{
+    const NAME: &'static str = <M as BlockingRequestHandler<R>>::NAME;
+    const FLAGS: &'static str = <M as BlockingRequestHandler<R>>::FLAGS;
+    const KEYS: CommandKeys = <M as BlockingRequestHandler<R>>::KEYS;
+
+    type Result = rm::RedisResult;
+
+    fn handle(&self, ctx: &rm::Context, req: Blocking<R>) -> Self::Result {
+        let req = req.0;
+
+        if let Some(result) = self.try_handle(ctx, &req) {
+            return result.into_reply();
+        }
+
+        if !can_block(ctx) {
+            return self.on_timeout(ctx, &req);
+        }
+
+        let keys: Vec<_> = self
+            .keys(&req)
+            .iter()
+            .map(|key| create_string(ctx, key))
+            .collect();
+        let mut keys: Vec<_> = keys.iter().map(|key| key.inner).collect();
+
+        let timeout = self
+            .timeout(&req)
+            .map_or(0, |timeout| timeout.as_millis().max(1) as i64);
+
+        // handlers only run on the instance kept by `InstanceMngr`, which outlives the client
+        let module = self as *const M as *mut ffi::c_void;
+
+        unsafe {
+            rm::raw::RedisModule_BlockClientOnKeys.unwrap()(
+                ctx.ctx,
+                Some(on_ready::<M, R>),
+                Some(on_timeout::<M, R>),
+                None,
+                timeout,
+                keys.as_mut_ptr(),
+                keys.len() as ffi::c_int,
+                module,
+            )
+        };
+
+        Ok(rm::RedisValue::NoReply)
+    }
+}
+
+/// Clients in `MULTI` or scripts cannot be blocked.
+fn can_block(ctx: &rm::Context) -> bool {
+    let flags = unsafe { rm::raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) } as u32;
+
+    flags & (rm::raw::REDISMODULE_CTX_FLAGS_MULTI | rm::raw::REDISMODULE_CTX_FLAGS_LUA) == 0
+}
+
+/// Instance and request of a blocked client, the request is parsed again from its arguments.
+unsafe fn blocked<'m, M, R>(
+    ctx: &rm::Context,
+    argv: *mut *mut rm::RedisModuleString,
+    argc: ffi::c_int,
+) -> (&'m M, Result<R, rm::RedisError>)
+where
+    R: Command,
+{
+    let module = rm::raw::RedisModule_GetBlockedClientPrivateData.unwrap()(ctx.ctx) as *const M;
+
+    (&*module, R::try_from(rm::decode_args(ctx.ctx, argv, argc)))
+}
+
+unsafe extern "C" fn on_ready<M, R>(
+    ctx: *mut rm::RedisModuleCtx,
+    argv: *mut *mut rm::RedisModuleString,
+    argc: ffi::c_int,
+) -> ffi::c_int
+where
+    M: BlockingRequestHandler<R>,
+    R: Command,
+{
+    let ctx = rm::Context::new(ctx);
+    let command = <M as BlockingRequestHandler<R>>::NAME;
+
+    let ready = guard::catch(command, || {
+        let (module, req) = blocked::<M, R>(&ctx, argv, argc);
+
+        match req {
+            Ok(req) => module.try_handle(&ctx, &req).map(IntoReply::into_reply),
+            Err(err) => Some(Err(err)),
+        }
+    });
+
+    let result = match ready {
+        Some(Some(result)) => result,
+        // keeps the client blocked
+        Some(None) => return rm::Status::Err as ffi::c_int,
+        None => guard::panicked(command),
+    };
+
+    ctx.reply(result);
+
+    rm::Status::Ok as ffi::c_int
+}
+
+unsafe extern "C" fn on_timeout<M, R>(
+    ctx: *mut rm::RedisModuleCtx,
+    argv: *mut *mut rm::RedisModuleString,
+    argc: ffi::c_int,
+) -> ffi::c_int
+where
+    M: BlockingRequestHandler<R>,
+    R: Command,
+{
+    let ctx = rm::Context::new(ctx);
+    let command = <M as BlockingRequestHandler<R>>::NAME;
+
+    let result = guard::catch(command, || {
+        let (module, req) = blocked::<M, R>(&ctx, argv, argc);
+
+        req.and_then(|req| module.on_timeout(&ctx, &req))
+    });
+
+    ctx.reply(result.unwrap_or_else(|| guard::panicked(command))) as ffi::c_int
+}
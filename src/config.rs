@@ -0,0 +1,744 @@
+use std::{
+    ffi::{self, CString},
+    ops::RangeInclusive,
+    ptr,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use redis_module as rm;
+use redis_module::NextArg as _;
+
+use crate::{guard, InstanceMngr, Module};
+
+pub trait Config: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> + Clone {
+    /// Checks the fields against each other, the error is logged on load and replied to
+    /// `CONFIG SET`.
+    fn validate(&self) -> Result<(), rm::RedisError> {
+        Ok(())
+    }
+
+    /// Fields registered as module configs, read and changed with `CONFIG GET/SET
+    /// <module>.<name>` and set by `MODULE LOADEX ... CONFIG`.
+    ///
+    /// The values parsed from the load arguments are the defaults.
+    fn options() -> Vec<ConfigOption<Self>> {
+        Vec::new()
+    }
+}
+
+/// Value of a `#[derive(Config)]` field, written as a load argument or a module config.
+pub trait ConfigValue: Sized {
+    fn parse_value(value: &str) -> Result<Self, String>;
+
+    fn format_value(&self) -> String;
+
+    /// Value of a field without argument nor default, `None` makes it required.
+    fn missing() -> Option<Self> {
+        None
+    }
+
+    /// Module config of a `#[config(option)]` field, a string config unless overridden.
+    ///
+    /// Integers and durations are numeric configs within `min..=max`, booleans are bool
+    /// configs and `#[derive(ConfigValue)]` enums are enum configs.
+    fn option<C: 'static>(
+        name: &'static str,
+        _min: Option<i64>,
+        _max: Option<i64>,
+        get: fn(&C) -> &Self,
+        get_mut: fn(&mut C) -> &mut Self,
+    ) -> ConfigOption<C>
+    where
+        Self: 'static,
+    {
+        ConfigOption::string(
+            name,
+            move |config| get(config).format_value(),
+            move |config, value| {
+                *get_mut(config) = Self::parse_value(&value)?;
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Integers within the range of both the type and `i64`.
+macro_rules! config_value_int {
+    ($($ty:ty),*) => {
+        $(
+            impl ConfigValue for $ty {
+                fn parse_value(value: &str) -> Result<Self, String> {
+                    value.parse().map_err(|err| format!("{}", err))
+                }
+
+                fn format_value(&self) -> String {
+                    self.to_string()
+                }
+
+                fn option<C: 'static>(
+                    name: &'static str,
+                    min: Option<i64>,
+                    max: Option<i64>,
+                    get: fn(&C) -> &Self,
+                    get_mut: fn(&mut C) -> &mut Self,
+                ) -> ConfigOption<C> {
+                    let lowest = i64::try_from(<$ty>::MIN).unwrap_or(i64::MIN);
+                    let highest = i64::try_from(<$ty>::MAX).unwrap_or(i64::MAX);
+
+                    ConfigOption::numeric(
+                        name,
+                        min.unwrap_or(lowest)..=max.unwrap_or(highest),
+                        move |config| i64::try_from(*get(config)).unwrap_or(highest),
+                        move |config, value| {
+                            *get_mut(config) = value.try_into().map_err(|err| format!("{}", err))?;
+                            Ok(())
+                        },
+                    )
+                }
+            }
+        )*
+    };
+}
+
+config_value_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+macro_rules! config_value_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl ConfigValue for $ty {
+                fn parse_value(value: &str) -> Result<Self, String> {
+                    value.parse().map_err(|err| format!("{}", err))
+                }
+
+                fn format_value(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+config_value_from_str!(String, f64);
+
+/// `yes`/`no`, as in the redis config, or `true`/`false`.
+impl ConfigValue for bool {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "yes" | "true" => Ok(true),
+            "no" | "false" => Ok(false),
+            _ => Err(format!("expected yes or no, got {}", value)),
+        }
+    }
+
+    fn format_value(&self) -> String {
+        if *self { "yes" } else { "no" }.to_string()
+    }
+
+    fn option<C: 'static>(
+        name: &'static str,
+        _min: Option<i64>,
+        _max: Option<i64>,
+        get: fn(&C) -> &Self,
+        get_mut: fn(&mut C) -> &mut Self,
+    ) -> ConfigOption<C> {
+        ConfigOption::bool(
+            name,
+            move |config| *get(config),
+            move |config, value| {
+                *get_mut(config) = value;
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Number with a `ms`, `s`, `m` or `h` unit, milliseconds without one.
+///
+/// The module config is numeric, in milliseconds.
+impl ConfigValue for Duration {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+
+        let (number, unit) = value.split_at(split);
+
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("expected a duration like 100ms or 5s, got {}", value))?;
+
+        let millis = match unit {
+            "" | "ms" => 1,
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            _ => return Err(format!("unknown duration unit {}", unit)),
+        };
+
+        number
+            .checked_mul(millis)
+            .map(Duration::from_millis)
+            .ok_or_else(|| format!("duration {} is too long", value))
+    }
+
+    fn format_value(&self) -> String {
+        let millis = self.as_millis();
+
+        let units = [(60 * 60 * 1000, "h"), (60 * 1000, "m"), (1000, "s")];
+
+        units
+            .iter()
+            .find(|(unit, _)| millis != 0 && millis % unit == 0)
+            .map_or_else(
+                || format!("{}ms", millis),
+                |(unit, suffix)| format!("{}{}", millis / unit, suffix),
+            )
+    }
+
+    fn option<C: 'static>(
+        name: &'static str,
+        min: Option<i64>,
+        max: Option<i64>,
+        get: fn(&C) -> &Self,
+        get_mut: fn(&mut C) -> &mut Self,
+    ) -> ConfigOption<C> {
+        ConfigOption::numeric(
+            name,
+            min.unwrap_or(0)..=max.unwrap_or(i64::MAX),
+            move |config| get(config).as_millis().try_into().unwrap_or(i64::MAX),
+            move |config, millis| {
+                let millis = u64::try_from(millis).map_err(|err| format!("{}", err))?;
+
+                *get_mut(config) = Duration::from_millis(millis);
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Optional field, an empty value is `None`.
+impl<T: ConfigValue> ConfigValue for Option<T> {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        T::parse_value(value).map(Some)
+    }
+
+    fn format_value(&self) -> String {
+        self.as_ref().map(T::format_value).unwrap_or_default()
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+/// Values of `keywords` in `KEYWORD value` load arguments, keywords are case insensitive.
+pub fn parse_args(
+    args: Vec<rm::RedisString>,
+    keywords: &[&str],
+) -> Result<Vec<Option<String>>, rm::RedisError> {
+    let mut values = vec![None; keywords.len()];
+    let mut args = args.into_iter();
+
+    while let Ok(arg) = args.next_string() {
+        let i = keywords
+            .iter()
+            .position(|keyword| keyword.eq_ignore_ascii_case(&arg))
+            .ok_or_else(|| rm::RedisError::String(format!("unknown argument: {}", arg)))?;
+
+        let value = args
+            .next_string()
+            .map_err(|_| rm::RedisError::String(format!("missing value of {}", keywords[i])))?;
+
+        values[i] = Some(value);
+    }
+
+    Ok(values)
+}
+
+/// Field `keyword` parsed from `value`, `default` is used without one.
+pub fn field<T, D>(keyword: &str, value: Option<String>, default: D) -> Result<T, rm::RedisError>
+where
+    T: ConfigValue,
+    D: FnOnce() -> Option<T>,
+{
+    let value = match value {
+        Some(value) => value,
+        None => {
+            return default()
+                .or_else(T::missing)
+                .ok_or_else(|| rm::RedisError::String(format!("missing {}", keyword)))
+        }
+    };
+
+    T::parse_value(&value).map_err(|err| rm::RedisError::String(format!("{}: {}", keyword, err)))
+}
+
+type Get<C, V> = Box<dyn Fn(&C) -> V + Send + Sync>;
+
+/// Sets a field, the error is replied to `CONFIG SET`.
+type Set<C, V> = Box<dyn Fn(&mut C, V) -> Result<(), String> + Send + Sync>;
+
+enum Kind<C> {
+    String(Get<C, String>, Set<C, String>),
+    Numeric(RangeInclusive<i64>, Get<C, i64>, Set<C, i64>),
+    Bool(Get<C, bool>, Set<C, bool>),
+    Enum(&'static [(&'static str, i32)], Get<C, i32>, Set<C, i32>),
+}
+
+/// Field of a [`Config`] registered as a module config, declared by `Config::options`.
+///
+/// Changes are validated with `Config::validate` and passed to `Module::reconfigure`.
+pub struct ConfigOption<C> {
+    name: &'static str,
+    flags: u32,
+    kind: Kind<C>,
+}
+
+impl<C> ConfigOption<C> {
+    pub fn string<G, S>(name: &'static str, get: G, set: S) -> Self
+    where
+        G: Fn(&C) -> String + Send + Sync + 'static,
+        S: Fn(&mut C, String) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self::new(name, Kind::String(Box::new(get), Box::new(set)))
+    }
+
+    /// Integer within `range`, bounds are checked by redis.
+    pub fn numeric<G, S>(name: &'static str, range: RangeInclusive<i64>, get: G, set: S) -> Self
+    where
+        G: Fn(&C) -> i64 + Send + Sync + 'static,
+        S: Fn(&mut C, i64) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self::new(name, Kind::Numeric(range, Box::new(get), Box::new(set)))
+    }
+
+    pub fn bool<G, S>(name: &'static str, get: G, set: S) -> Self
+    where
+        G: Fn(&C) -> bool + Send + Sync + 'static,
+        S: Fn(&mut C, bool) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self::new(name, Kind::Bool(Box::new(get), Box::new(set)))
+    }
+
+    /// One of the names of `values`, passed as the value paired with it.
+    pub fn enumeration<G, S>(
+        name: &'static str,
+        values: &'static [(&'static str, i32)],
+        get: G,
+        set: S,
+    ) -> Self
+    where
+        G: Fn(&C) -> i32 + Send + Sync + 'static,
+        S: Fn(&mut C, i32) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self::new(name, Kind::Enum(values, Box::new(get), Box::new(set)))
+    }
+
+    /// Only set when the module is loaded, `CONFIG SET` fails.
+    pub fn immutable(mut self) -> Self {
+        self.flags |= rm::raw::REDISMODULE_CONFIG_IMMUTABLE;
+        self
+    }
+
+    /// Hidden from `CONFIG GET` and logs, for secrets.
+    pub fn sensitive(mut self) -> Self {
+        self.flags |= rm::raw::REDISMODULE_CONFIG_SENSITIVE;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn new(name: &'static str, kind: Kind<C>) -> Self {
+        Self {
+            name,
+            flags: rm::raw::REDISMODULE_CONFIG_DEFAULT,
+            kind,
+        }
+    }
+}
+
+struct Registry<M: Module> {
+    /// Config with the values set so far.
+    config: Mutex<M::Config>,
+    options: Vec<ConfigOption<M::Config>>,
+    instance: fn() -> Option<&'static M>,
+}
+
+/// Privdata of one registered option.
+struct Slot<M: Module> {
+    registry: &'static Registry<M>,
+    index: usize,
+    /// Last value returned by `get_string`, owned by the module until the next call.
+    value: Mutex<*mut rm::RedisModuleString>,
+}
+
+impl<M: Module> Slot<M> {
+    fn kind(&self) -> &Kind<M::Config> {
+        &self.registry.options[self.index].kind
+    }
+
+    fn name(&self) -> &'static str {
+        self.registry.options[self.index].name
+    }
+
+    fn config(&self) -> MutexGuard<'_, M::Config> {
+        self.registry.config.lock().unwrap()
+    }
+
+    /// Reads the field, a panic reads `T::default()`.
+    fn get<T, F>(&self, get: F) -> T
+    where
+        T: Default,
+        F: FnOnce(&Kind<M::Config>, &M::Config) -> T,
+    {
+        let config = self.config();
+
+        guard::catch(format_args!("config {} get", self.name()), || {
+            get(self.kind(), &config)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Sets the field on a copy, the config is only replaced if the value is accepted.
+    fn set<F>(&self, err: *mut *mut rm::RedisModuleString, set: F) -> ffi::c_int
+    where
+        F: FnOnce(&Kind<M::Config>, &mut M::Config) -> Result<(), String>,
+    {
+        let mut config = self.config();
+        let mut next = config.clone();
+
+        let result = guard::catch(format_args!("config {} set", self.name()), || {
+            set(self.kind(), &mut next)
+        });
+
+        match result {
+            Some(Ok(())) => {
+                *config = next;
+
+                rm::raw::REDISMODULE_OK as ffi::c_int
+            }
+            Some(Err(msg)) => fail(err, &msg),
+            None => fail(err, "value rejected, the module panicked"),
+        }
+    }
+}
+
+/// Registers `Config::options` and loads the values passed to the module, `config` holds the
+/// values parsed from the load arguments.
+pub(crate) fn register<M, G>(ctx: &rm::Context, config: M::Config) -> Result<M::Config, String>
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let options = M::Config::options();
+
+    if options.is_empty() {
+        return Ok(config);
+    }
+
+    // configs are registered for the module lifetime
+    let registry: &'static Registry<M> = Box::leak(Box::new(Registry {
+        config: Mutex::new(config),
+        options,
+        instance: G::get,
+    }));
+
+    for index in 0..registry.options.len() {
+        let slot = Box::leak(Box::new(Slot {
+            registry,
+            index,
+            value: Mutex::new(ptr::null_mut()),
+        }));
+
+        register_option(ctx, slot)?;
+    }
+
+    let status = rm::Status::from(unsafe { rm::raw::RedisModule_LoadConfigs.unwrap()(ctx.ctx) });
+
+    if status == rm::Status::Err {
+        return Err("cannot load configs".to_string());
+    }
+
+    Ok(registry.config.lock().unwrap().clone())
+}
+
+fn register_option<M>(ctx: &rm::Context, slot: &'static Slot<M>) -> Result<(), String>
+where
+    M: Module + 'static,
+{
+    let option = &slot.registry.options[slot.index];
+    let config = slot.config().clone();
+
+    let name = CString::new(option.name).map_err(|_| format!("invalid name {}", option.name))?;
+    let flags = option.flags as ffi::c_uint;
+    let privdata = slot as *const Slot<M> as *mut ffi::c_void;
+
+    let status = match &option.kind {
+        Kind::String(get, _) => {
+            let default = CString::new(get(&config))
+                .map_err(|_| format!("invalid default of {}", option.name))?;
+
+            unsafe {
+                rm::raw::RedisModule_RegisterStringConfig.unwrap()(
+                    ctx.ctx,
+                    name.as_ptr(),
+                    default.as_ptr(),
+                    flags,
+                    Some(get_string::<M>),
+                    Some(set_string::<M>),
+                    Some(apply::<M>),
+                    privdata,
+                )
+            }
+        }
+        Kind::Numeric(range, get, _) => unsafe {
+            rm::raw::RedisModule_RegisterNumericConfig.unwrap()(
+                ctx.ctx,
+                name.as_ptr(),
+                get(&config),
+                flags,
+                *range.start(),
+                *range.end(),
+                Some(get_numeric::<M>),
+                Some(set_numeric::<M>),
+                Some(apply::<M>),
+                privdata,
+            )
+        },
+        Kind::Bool(get, _) => unsafe {
+            rm::raw::RedisModule_RegisterBoolConfig.unwrap()(
+                ctx.ctx,
+                name.as_ptr(),
+                get(&config) as ffi::c_int,
+                flags,
+                Some(get_bool::<M>),
+                Some(set_bool::<M>),
+                Some(apply::<M>),
+                privdata,
+            )
+        },
+        Kind::Enum(values, get, _) => {
+            let names = values
+                .iter()
+                .map(|(name, _)| CString::new(*name))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid value name of {}", option.name))?;
+
+            let mut name_ptrs: Vec<_> = names.iter().map(|name| name.as_ptr()).collect();
+            let ints: Vec<_> = values
+                .iter()
+                .map(|(_, value)| *value as ffi::c_int)
+                .collect();
+
+            unsafe {
+                rm::raw::RedisModule_RegisterEnumConfig.unwrap()(
+                    ctx.ctx,
+                    name.as_ptr(),
+                    get(&config),
+                    flags,
+                    name_ptrs.as_mut_ptr(),
+                    ints.as_ptr(),
+                    ints.len() as ffi::c_int,
+                    Some(get_enum::<M>),
+                    Some(set_enum::<M>),
+                    Some(apply::<M>),
+                    privdata,
+                )
+            }
+        }
+    };
+
+    match rm::Status::from(status) {
+        rm::Status::Ok => Ok(()),
+        rm::Status::Err => Err(format!("cannot register config {}", option.name)),
+    }
+}
+
+unsafe fn slot<'s, M: Module>(privdata: *mut ffi::c_void) -> &'s Slot<M> {
+    &*(privdata as *const Slot<M>)
+}
+
+/// Replies `msg` to `CONFIG SET`, redis frees the error string.
+fn fail(err: *mut *mut rm::RedisModuleString, msg: &str) -> ffi::c_int {
+    unsafe {
+        *err = rm::raw::RedisModule_CreateString.unwrap()(
+            ptr::null_mut(),
+            msg.as_ptr().cast(),
+            msg.len(),
+        );
+    }
+
+    rm::raw::REDISMODULE_ERR as ffi::c_int
+}
+
+// every option is registered with the callbacks of its kind, other kinds are unreachable
+
+unsafe extern "C" fn get_string<M: Module>(
+    _name: *const ffi::c_char,
+    privdata: *mut ffi::c_void,
+) -> *mut rm::RedisModuleString {
+    let slot = slot::<M>(privdata);
+
+    let value = slot.get(|kind, config| match kind {
+        Kind::String(get, _) => get(config),
+        _ => unreachable!(),
+    });
+
+    let mut prev = slot.value.lock().unwrap();
+
+    if !prev.is_null() {
+        rm::raw::RedisModule_FreeString.unwrap()(ptr::null_mut(), *prev);
+    }
+
+    *prev = rm::raw::RedisModule_CreateString.unwrap()(
+        ptr::null_mut(),
+        value.as_ptr().cast(),
+        value.len(),
+    );
+
+    *prev
+}
+
+unsafe extern "C" fn set_string<M: Module>(
+    _name: *const ffi::c_char,
+    value: *mut rm::RedisModuleString,
+    privdata: *mut ffi::c_void,
+    err: *mut *mut rm::RedisModuleString,
+) -> ffi::c_int {
+    let value = match rm::RedisString::from_ptr(value) {
+        Ok(value) => value.to_string(),
+        Err(_) => return fail(err, "value is not valid utf-8"),
+    };
+
+    slot::<M>(privdata).set(err, |kind, config| match kind {
+        Kind::String(_, set) => set(config, value),
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn get_numeric<M: Module>(
+    _name: *const ffi::c_char,
+    privdata: *mut ffi::c_void,
+) -> ffi::c_longlong {
+    slot::<M>(privdata).get(|kind, config| match kind {
+        Kind::Numeric(_, get, _) => get(config),
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn set_numeric<M: Module>(
+    _name: *const ffi::c_char,
+    value: ffi::c_longlong,
+    privdata: *mut ffi::c_void,
+    err: *mut *mut rm::RedisModuleString,
+) -> ffi::c_int {
+    slot::<M>(privdata).set(err, |kind, config| match kind {
+        Kind::Numeric(_, _, set) => set(config, value),
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn get_bool<M: Module>(
+    _name: *const ffi::c_char,
+    privdata: *mut ffi::c_void,
+) -> ffi::c_int {
+    slot::<M>(privdata).get(|kind, config| match kind {
+        Kind::Bool(get, _) => get(config) as ffi::c_int,
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn set_bool<M: Module>(
+    _name: *const ffi::c_char,
+    value: ffi::c_int,
+    privdata: *mut ffi::c_void,
+    err: *mut *mut rm::RedisModuleString,
+) -> ffi::c_int {
+    slot::<M>(privdata).set(err, |kind, config| match kind {
+        Kind::Bool(_, set) => set(config, value != 0),
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn get_enum<M: Module>(
+    _name: *const ffi::c_char,
+    privdata: *mut ffi::c_void,
+) -> ffi::c_int {
+    slot::<M>(privdata).get(|kind, config| match kind {
+        Kind::Enum(_, get, _) => get(config),
+        _ => unreachable!(),
+    })
+}
+
+unsafe extern "C" fn set_enum<M: Module>(
+    _name: *const ffi::c_char,
+    value: ffi::c_int,
+    privdata: *mut ffi::c_void,
+    err: *mut *mut rm::RedisModuleString,
+) -> ffi::c_int {
+    slot::<M>(privdata).set(err, |kind, config| match kind {
+        Kind::Enum(_, _, set) => set(config, value),
+        _ => unreachable!(),
+    })
+}
+
+/// Called once the values of a `CONFIG SET` are set, redis restores the previous values if the
+/// config is rejected.
+unsafe extern "C" fn apply<M: Module>(
+    ctx: *mut rm::RedisModuleCtx,
+    privdata: *mut ffi::c_void,
+    err: *mut *mut rm::RedisModuleString,
+) -> ffi::c_int {
+    let registry = slot::<M>(privdata).registry;
+    let config = registry.config.lock().unwrap().clone();
+
+    let applied = guard::catch("config apply", || {
+        Config::validate(&config).map_err(|error| error.to_string())?;
+
+        match (registry.instance)() {
+            Some(instance) => instance
+                .reconfigure(&rm::Context::new(ctx), &config)
+                .map_err(|error| error.to_string()),
+            None => Ok(()),
+        }
+    });
+
+    match applied {
+        Some(Ok(())) => rm::raw::REDISMODULE_OK as ffi::c_int,
+        Some(Err(msg)) => fail(err, &msg),
+        None => fail(err, "config rejected, the module panicked"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ConfigValue;
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            Duration::parse_value("100ms"),
+            Ok(Duration::from_millis(100))
+        );
+        assert_eq!(Duration::parse_value("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(Duration::parse_value("3000"), Ok(Duration::from_secs(3)));
+        assert!(Duration::parse_value("5d").is_err());
+
+        assert_eq!(Duration::from_secs(90).format_value(), "90s");
+        assert_eq!(Duration::from_secs(7200).format_value(), "2h");
+        assert_eq!(Duration::from_millis(1500).format_value(), "1500ms");
+
+        assert_eq!(bool::parse_value("YES"), Ok(true));
+        assert_eq!(Option::<u64>::parse_value(""), Ok(None));
+        assert_eq!(Option::<u64>::missing(), Some(None));
+    }
+}
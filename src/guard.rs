@@ -0,0 +1,71 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use redis_module as rm;
+
+/// Runs `f` from a callback called by redis, a panic cannot unwind into it.
+///
+/// The panic is logged with `what` and `None` returned, for the callback to fail instead.
+pub(crate) fn catch<T, F, D>(what: D, f: F) -> Option<T>
+where
+    F: FnOnce() -> T,
+    D: fmt::Display,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => Some(value),
+        Err(payload) => {
+            log::error!(target: "module", "{} panicked: {}", what, message(payload.as_ref()));
+
+            None
+        }
+    }
+}
+
+/// Reply for a command whose handler panicked.
+pub(crate) fn panicked(command: &str) -> rm::RedisResult {
+    Err(rm::RedisError::String(format!("ERR {} panicked", command)))
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        return message;
+    }
+
+    match payload.downcast_ref::<String>() {
+        Some(message) => message,
+        None => "unknown panic",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_module as rm;
+
+    use super::{catch, panicked};
+
+    #[test]
+    fn catches_panics() {
+        assert_eq!(catch("ok", || 1), Some(1));
+        assert_eq!(catch("str", || -> i32 { panic!("boom") }), None);
+        assert_eq!(
+            catch("string", || -> i32 { panic!("task {} missed", 7) }),
+            None
+        );
+
+        match panicked("task_create") {
+            Err(rm::RedisError::String(err)) => assert_eq!(err, "ERR task_create panicked"),
+            _ => panic!("not a string error"),
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let payload = std::panic::catch_unwind(|| panic!("task {} missed", 7)).unwrap_err();
+
+        assert_eq!(super::message(payload.as_ref()), "task 7 missed");
+        assert_eq!(super::message(&1u8), "unknown panic");
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    mem,
+    rc::Rc,
+    sync::Arc,
+    time,
+};
+
+/// Estimate of the heap memory owned by a value.
+pub trait HeapSize {
+    /// Bytes allocated on the heap, excluding `size_of::<Self>()`.
+    fn heap_size(&self) -> usize;
+
+    /// Bytes used by the value including its inline size.
+    fn total_size(&self) -> usize
+    where
+        Self: Sized,
+    {
+        mem::size_of::<Self>() + self.heap_size()
+    }
+}
+
+macro_rules! heap_size_zero {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl HeapSize for $ty {
+                #[inline]
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+heap_size_zero![
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    time::Duration,
+];
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        mem::size_of::<T>() + T::heap_size(self)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+// shared values are accounted in full by every owner
+impl<T: HeapSize> HeapSize for Arc<T> {
+    fn heap_size(&self) -> usize {
+        T::total_size(self)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Rc<T> {
+    fn heap_size(&self) -> usize {
+        T::total_size(self)
+    }
+}
+
+// hash tables store one control byte per bucket next to the entries
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_size(&self) -> usize {
+        let buckets = self.capacity() * (mem::size_of::<(K, V)>() + 1);
+
+        buckets
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_size() + v.heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_size(&self) -> usize {
+        let buckets = self.capacity() * (mem::size_of::<T>() + 1);
+
+        buckets + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+// b-tree nodes are not exposed, entries are a lower bound
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_size(&self) -> usize {
+        self.iter()
+            .map(|(k, v)| k.total_size() + v.total_size())
+            .sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::total_size).sum()
+    }
+}
+
+macro_rules! heap_size_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: HeapSize),+> HeapSize for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn heap_size(&self) -> usize {
+                let ($($name,)+) = self;
+
+                0 $( + $name.heap_size() )+
+            }
+        }
+    };
+}
+
+heap_size_tuple!(A);
+heap_size_tuple!(A, B);
+heap_size_tuple!(A, B, C);
+heap_size_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, mem};
+
+    use super::HeapSize;
+
+    #[test]
+    fn containers() {
+        let strings = vec![String::with_capacity(10), String::with_capacity(20)];
+
+        assert_eq!(strings.heap_size(), 2 * mem::size_of::<String>() + 30);
+
+        let mut map = HashMap::with_capacity(4);
+        map.insert(1u64, String::with_capacity(8));
+
+        let buckets = map.capacity() * (mem::size_of::<(u64, String)>() + 1);
+
+        assert_eq!(map.heap_size(), buckets + 8);
+        assert_eq!(Some(Box::new(1u32)).heap_size(), mem::size_of::<u32>());
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    ptr,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+use redis_module as rm;
+
+use crate::{guard, InstanceMngr, Module};
+
+/// Wait between attempts to take the redis lock, see [`JobContext::lock`].
+const LOCK_RETRY: Duration = Duration::from_millis(1);
+
+type Run<M> = Box<dyn FnOnce(&M, &JobContext) + Send>;
+
+type Instance<M> = fn() -> Option<&'static M>;
+
+struct Pending<M> {
+    name: String,
+    run: Run<M>,
+}
+
+struct State<M> {
+    /// Jobs spawned before the module was bound, started by `bind`.
+    pending: Vec<Pending<M>>,
+    running: Vec<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Stop {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// Background jobs of the module, running on their own threads outside of any client.
+///
+/// Jobs spawned in `Module::create` start once the module is loaded. On unload every job is
+/// told to stop and joined before `Module::stop`.
+pub struct Jobs<M> {
+    state: Mutex<State<M>>,
+    stop: Arc<Stop>,
+    instance: OnceCell<Instance<M>>,
+}
+
+impl<M> Default for Jobs<M> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State {
+                pending: Vec::new(),
+                running: Vec::new(),
+            }),
+            stop: Arc::default(),
+            instance: OnceCell::new(),
+        }
+    }
+}
+
+impl<M: Sync + 'static> Jobs<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` once on a thread named `name`.
+    pub fn spawn<F>(&self, name: &str, f: F)
+    where
+        F: FnOnce(&M, &JobContext) + Send + 'static,
+    {
+        let pending = Pending {
+            name: name.to_string(),
+            run: Box::new(f),
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        match self.instance.get() {
+            Some(&instance) => start(&mut state, instance, &self.stop, pending),
+            None => state.pending.push(pending),
+        }
+    }
+
+    /// Runs `f` every `interval` until the module is unloaded, a panicked run is logged.
+    pub fn every<F>(&self, name: &str, interval: Duration, mut f: F)
+    where
+        F: FnMut(&M, &JobContext) + Send + 'static,
+    {
+        let what = format!("job {}", name);
+
+        self.spawn(name, move |module, job| {
+            while job.sleep(interval) {
+                guard::catch(&what, || f(module, job));
+            }
+        })
+    }
+
+    /// Tells every job to stop and waits for them, jobs not started yet are dropped.
+    pub fn stop_all(&self) {
+        *self.stop.stopped.lock().unwrap() = true;
+        self.stop.wake.notify_all();
+
+        let running = {
+            let mut state = self.state.lock().unwrap();
+
+            state.pending.clear();
+            mem::take(&mut state.running)
+        };
+
+        for handle in running {
+            let name = handle.thread().name().unwrap_or_default().to_string();
+
+            if handle.join().is_err() {
+                log::error!(target: "jobs", "job {} panicked", name);
+            }
+        }
+    }
+}
+
+fn start<M: Sync + 'static>(
+    state: &mut State<M>,
+    instance: Instance<M>,
+    stop: &Arc<Stop>,
+    pending: Pending<M>,
+) {
+    let stop = stop.clone();
+    let run = pending.run;
+
+    let spawned = thread::Builder::new()
+        .name(pending.name.clone())
+        .spawn(move || {
+            let job = JobContext::new(stop);
+
+            // the lock is held while the module loads, the instance is set once it is taken
+            if job.lock().is_none() {
+                return;
+            }
+
+            if let Some(module) = instance() {
+                run(module, &job);
+            }
+        });
+
+    match spawned {
+        Ok(handle) => state.running.push(handle),
+        Err(err) => log::error!(target: "jobs", "cannot spawn job {}: {}", pending.name, err),
+    }
+}
+
+/// Starts the jobs of the module, if any, spawned so far.
+pub(crate) fn bind<M, G>(module: &M)
+where
+    M: Module + Sync + 'static,
+    G: InstanceMngr<M>,
+{
+    let jobs = match module.jobs() {
+        Some(jobs) => jobs,
+        None => return,
+    };
+
+    // jobs spawned from now on start right away, they see the instance once it is set
+    let _ = jobs.instance.set(G::get);
+
+    let mut state = jobs.state.lock().unwrap();
+
+    for pending in mem::take(&mut state.pending) {
+        start(&mut state, G::get, &jobs.stop, pending);
+    }
+}
+
+/// Thread safe context of a job, detached from any client.
+pub struct JobContext {
+    ctx: *mut rm::RedisModuleCtx,
+    stop: Arc<Stop>,
+}
+
+impl JobContext {
+    fn new(stop: Arc<Stop>) -> Self {
+        let ctx = unsafe { rm::raw::RedisModule_GetThreadSafeContext.unwrap()(ptr::null_mut()) };
+
+        Self { ctx, stop }
+    }
+
+    /// Set once the module is unloading, long jobs should check it and return.
+    pub fn is_stopped(&self) -> bool {
+        *self.stop.stopped.lock().unwrap()
+    }
+
+    /// Sleeps for `timeout`, `false` if the module started unloading meanwhile.
+    pub fn sleep(&self, timeout: Duration) -> bool {
+        let stopped = self.stop.stopped.lock().unwrap();
+
+        let (stopped, _) = self
+            .stop
+            .wake
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap();
+
+        !*stopped
+    }
+
+    /// Takes the redis lock until the guard is dropped, `None` once the module is unloading.
+    ///
+    /// Stores are only accessed through the guard. The lock is retried every millisecond
+    /// instead of waited for, as unload holds it while joining the jobs, so a busy server can
+    /// keep it from a job for long. Jobs that must not wait unboundedly use `lock_timeout`.
+    pub fn lock(&self) -> Option<Locked<'_>> {
+        self.try_lock_until(None)
+    }
+
+    /// Takes the redis lock like `lock`, also `None` if it is not taken within `timeout`.
+    pub fn lock_timeout(&self, timeout: Duration) -> Option<Locked<'_>> {
+        self.try_lock_until(Instant::now().checked_add(timeout))
+    }
+
+    fn try_lock_until(&self, deadline: Option<Instant>) -> Option<Locked<'_>> {
+        loop {
+            let status = rm::Status::from(unsafe {
+                rm::raw::RedisModule_ThreadSafeContextTryLock.unwrap()(self.ctx)
+            });
+
+            if status == rm::Status::Ok {
+                return Some(Locked {
+                    ctx: rm::Context::new(self.ctx),
+                    marker: PhantomData,
+                });
+            }
+
+            let retry = match deadline {
+                Some(deadline) => LOCK_RETRY.min(deadline.checked_duration_since(Instant::now())?),
+                None => LOCK_RETRY,
+            };
+
+            if retry.is_zero() || !self.sleep(retry) {
+                return None;
+            }
+        }
+    }
+}
+
+impl Drop for JobContext {
+    fn drop(&mut self) {
+        unsafe { rm::raw::RedisModule_FreeThreadSafeContext.unwrap()(self.ctx) }
+    }
+}
+
+/// Redis lock taken by [`JobContext::lock`], released on drop.
+pub struct Locked<'j> {
+    ctx: rm::Context,
+    marker: PhantomData<&'j JobContext>,
+}
+
+impl Deref for Locked<'_> {
+    type Target = rm::Context;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ctx
+    }
+}
+
+impl Drop for Locked<'_> {
+    fn drop(&mut self) {
+        unsafe { rm::raw::RedisModule_ThreadSafeContextUnlock.unwrap()(self.ctx.ctx) }
+    }
+}
@@ -1,10 +1,25 @@
 mod arg_ext;
+mod async_requests;
+mod aux_data;
+mod blocking_requests;
+mod config;
+mod guard;
+mod heap_size;
+mod jobs;
 #[macro_use]
 mod macros;
 mod logger;
+mod middleware;
 mod redis_io;
+mod reply;
 mod requests;
+mod serialize;
 mod store;
+mod timers;
+
+// the derives expand to `::redismod` paths, the crate tests use them too
+#[cfg(test)]
+extern crate self as redismod;
 
 use std::{
     marker::PhantomData,
@@ -13,23 +28,75 @@ use std::{
 
 use redis_module as rm;
 
+pub use config::{field as __config_field, parse_args as __config_args};
+pub use log as __log;
 pub use once_cell::sync::OnceCell as __OnceCell;
+pub use redis_module as __rm;
+pub use store::use_redis_alloc as __use_redis_alloc;
+
+pub use redismod_derive::{Command, Config, ConfigValue, HeapSize, RedisType};
+
+pub use arg_ext::{CommandArgs, FromArgs, NextArgExt};
+
+pub use async_requests::{Async, AsyncRequestHandler, Cancelled, Job};
+
+pub use aux_data::AuxWhen;
+
+pub use blocking_requests::{Blocking, BlockingRequestHandler};
+
+pub use config::{Config, ConfigOption, ConfigValue};
+
+pub use heap_size::HeapSize;
 
-pub use arg_ext::{FromArgs, NextArgExt};
+pub use jobs::{JobContext, Jobs, Locked};
 
 pub use redis_io::{IOLoader, IOSaver, Loader, Saver};
 
+pub use middleware::Middleware;
+
+pub use reply::{IntoRedisError, IntoReply};
+
 pub use requests::{Command, CommandKeys, RequestHandler, Requests};
 
-pub use store::{Entry, EntryMut, Error, ModuleStores, Store, Stores, Type, Types};
+pub use serialize::{
+    from_loader,
+    from_loader_marked,
+    to_saver,
+    to_saver_checked,
+    to_saver_marked,
+    Deserializer,
+    Error as SerializeError,
+    Serializer,
+};
 
-pub trait Config: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> {
-    fn validate(&self) -> Result<(), ()> {
-        Ok(())
-    }
-}
+pub use store::{
+    AofEmitter,
+    DefaultCodec,
+    Defrag,
+    DefragCtx,
+    Digest,
+    Entry,
+    EntryMut,
+    Error,
+    HashTag,
+    Index,
+    Initial,
+    KeyCodec,
+    KeyId,
+    MigrateFrom,
+    Migrations,
+    ModuleStores,
+    Namespaced,
+    Scan,
+    Schema,
+    Store,
+    Stores,
+    Type,
+    Types,
+    Upgrade,
+};
 
-impl<R> Config for R where R: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> {}
+pub use timers::{TimerId, Timers};
 
 pub trait InstanceMngr<M: Module> {
     fn set(module: M);
@@ -40,10 +107,59 @@ pub trait Module: Sized {
     const VERSION: i32;
     const NAME: &'static str;
 
-    type Error: std::error::Error;
+    type Error: std::error::Error + IntoRedisError;
     type Config: Config;
     type Requests: Requests<Self>;
     type DataTypes: Types;
+    /// Payload of the module [`Timers`], `()` if the module has none.
+    type Timer;
+
+    /// Name of the data type carrying module aux data, 9 chars like `Type::REDIS_NAME`.
+    ///
+    /// `None` disables `aux_save`/`aux_load`.
+    const AUX_NAME: Option<&'static str> = None;
+    const AUX_VERSION: i32 = 1;
+    const AUX_TRIGGERS: &'static [AuxWhen] = &[AuxWhen::BeforeKeyspace];
+
+    /// Threads running the jobs of `Async` requests, started with the first request.
+    const ASYNC_WORKERS: usize = 4;
+    /// Jobs waiting for a worker, requests beyond it are replied a `BUSY` error.
+    const ASYNC_QUEUE: usize = 1024;
+
+    /// Saves module state not bound to a key, once per trigger in `AUX_TRIGGERS`.
+    fn aux_save(&self, _saver: &IOSaver, _when: AuxWhen) {}
+
+    fn aux_load(
+        &self,
+        _loader: &IOLoader,
+        _encver: usize,
+        _when: AuxWhen,
+    ) -> Result<(), rm::error::Error> {
+        Ok(())
+    }
+
+    /// Timers dispatching to `on_timer`, usually a field of the module.
+    fn timers(&self) -> Option<&Timers<Self::Timer>> {
+        None
+    }
+
+    fn on_timer(&self, _ctx: &rm::Context, _payload: Self::Timer) {}
+
+    /// Background jobs, stopped and joined on unload before `stop`.
+    fn jobs(&self) -> Option<&Jobs<Self>> {
+        None
+    }
+
+    /// Hooks run around every request handler, e.g. a tuple of middlewares kept by the module.
+    fn middleware(&self) -> Option<&dyn Middleware<Self>> {
+        None
+    }
+
+    /// Called with the new config once `CONFIG SET` changed `Config::options`, an error
+    /// rejects the change.
+    fn reconfigure(&self, _ctx: &rm::Context, _config: &Self::Config) -> Result<(), Self::Error> {
+        Ok(())
+    }
 
     fn stop(&self, _ctx: &rm::Context) -> Result<(), Self::Error> {
         Ok(())
@@ -70,13 +186,27 @@ where
 
 impl<M, G> Instance<M, G>
 where
-    M: Module + 'static,
+    M: Module + Sync + 'static,
     G: InstanceMngr<M>,
 {
+    /// `RedisModule_OnLoad`, a panic fails the load.
     pub fn on_load(
         ctx: *mut rm::RedisModuleCtx,
         argv: *mut *mut rm::RedisModuleString,
         argc: c_int,
+    ) -> rm::Status {
+        guard::catch("module load", || Self::load(ctx, argv, argc)).unwrap_or(rm::Status::Err)
+    }
+
+    /// `RedisModule_OnUnload`, a panic fails the unload.
+    pub fn on_unload(ctx: *mut rm::RedisModuleCtx) -> rm::Status {
+        guard::catch("module unload", || Self::unload(ctx)).unwrap_or(rm::Status::Err)
+    }
+
+    fn load(
+        ctx: *mut rm::RedisModuleCtx,
+        argv: *mut *mut rm::RedisModuleString,
+        argc: c_int,
     ) -> rm::Status {
         let ctx = &rm::Context::new(ctx);
 
@@ -101,6 +231,15 @@ where
             }
         };
 
+        let config = match config::register::<M, G>(ctx, config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("cannot register config: {:?}", err);
+
+                return rm::Status::Err;
+            }
+        };
+
         if let Err(err) = Config::validate(&config) {
             log::error!("config validate failed: {:?}", err);
 
@@ -115,6 +254,12 @@ where
             return rm::Status::Err;
         }
 
+        if let Err(err) = aux_data::register::<M, G>(ctx) {
+            log::error!("cannot register aux data type: {:?}", err);
+
+            return rm::Status::Err;
+        }
+
         let mut module = match M::create(ctx, config, stores) {
             Ok(module) => module,
             Err(err) => {
@@ -124,6 +269,14 @@ where
             }
         };
 
+        if let Err(err) = timers::bind::<M, G>(ctx, &module) {
+            log::error!("timers bind failed: {:?}", err);
+
+            return rm::Status::Err;
+        }
+
+        jobs::bind::<M, G>(&module);
+
         if let Err(err) = M::Requests::register::<G>(ctx) {
             log::error!("requests register failed: {:?}", err);
 
@@ -141,7 +294,7 @@ where
         rm::Status::Ok
     }
 
-    pub fn on_unload(ctx: *mut rm::RedisModuleCtx) -> rm::Status {
+    fn unload(ctx: *mut rm::RedisModuleCtx) -> rm::Status {
         let instance = match G::get() {
             Some(instance) => instance,
             None => return rm::Status::Err,
@@ -149,6 +302,14 @@ where
 
         let ctx = &rm::Context::new(ctx);
 
+        if let Some(jobs) = instance.jobs() {
+            jobs.stop_all();
+        }
+
+        if let Some(timers) = instance.timers() {
+            timers.stop_all(ctx);
+        }
+
         match instance.stop(ctx) {
             Ok(_) => rm::Status::Ok,
             Err(_) => rm::Status::Err,
@@ -1,6 +1,20 @@
+/// Exports `RedisModule_OnLoad` and `RedisModule_OnUnload` for the module `M`.
+///
+/// `module![M, allocator]` also installs the redis allocator as the global allocator, module
+/// memory is then accounted by redis and `Type::defrag` is called by active defrag.
 #[macro_export]
 macro_rules! module {
     ($module:ident) => {
+        $crate::module!(@entry $module, false);
+    };
+    ($module:ident, allocator) => {
+        // module memory is accounted by redis and can be moved by active defrag
+        #[global_allocator]
+        static ALLOCATOR: redis_module::alloc::RedisAlloc = redis_module::alloc::RedisAlloc;
+
+        $crate::module!(@entry $module, true);
+    };
+    (@entry $module:ident, $redis_alloc:literal) => {
         static INSTANCE: $crate::__OnceCell<$module> = $crate::__OnceCell::new();
 
         type __Instance = $crate::Instance<$module, __InstanceMngr>;
@@ -26,6 +40,10 @@ macro_rules! module {
             argv: *mut *mut redis_module::raw::RedisModuleString,
             argc: std::os::raw::c_int,
         ) -> std::os::raw::c_int {
+            if $redis_alloc {
+                $crate::__use_redis_alloc();
+            }
+
             __Instance::on_load(ctx, argv, argc) as std::os::raw::c_int
         }
 
@@ -0,0 +1,219 @@
+use std::any::Any;
+
+use redis_module as rm;
+
+/// Hooks run around every `RequestHandler::handle` of the module, set by `Module::middleware`.
+///
+/// `before` runs once the request is parsed and validated, then `around` wraps the handler,
+/// then `after` sees its reply. Tuples chain middlewares: `before` and `around` run in order,
+/// `after` in reverse order. Async and blocking handlers reply later, `after` sees `NoReply`.
+pub trait Middleware<M> {
+    /// Runs before the handler, an error is replied instead of running it.
+    ///
+    /// `command` is the handler `NAME` and `request` the parsed `Command`, `R` for `Async<R>`
+    /// and `Blocking<R>`, see `Command::request`.
+    fn before(
+        &self,
+        _ctx: &rm::Context,
+        _module: &M,
+        _command: &str,
+        _request: &dyn Any,
+    ) -> Result<(), rm::RedisError> {
+        Ok(())
+    }
+
+    /// Wraps the handler, or the next middlewares of a tuple, run by `next`.
+    fn around(
+        &self,
+        _ctx: &rm::Context,
+        _module: &M,
+        _command: &str,
+        next: &mut dyn FnMut() -> rm::RedisResult,
+    ) -> rm::RedisResult {
+        next()
+    }
+
+    /// Sees the reply of the handler and may replace it.
+    fn after(
+        &self,
+        _ctx: &rm::Context,
+        _module: &M,
+        _command: &str,
+        _result: &mut rm::RedisResult,
+    ) {
+    }
+}
+
+impl<M> Middleware<M> for () {}
+
+/// Runs `handle` through the `around` and `after` hooks of `middleware`.
+pub(crate) fn wrap<M, F>(
+    middleware: &dyn Middleware<M>,
+    ctx: &rm::Context,
+    module: &M,
+    command: &str,
+    handle: F,
+) -> rm::RedisResult
+where
+    F: FnOnce() -> rm::RedisResult,
+{
+    let mut handle = Some(handle);
+
+    let mut result = middleware.around(ctx, module, command, &mut || match handle.take() {
+        Some(handle) => handle(),
+        None => Err(rm::RedisError::Str("ERR request already handled")),
+    });
+
+    middleware.after(ctx, module, command, &mut result);
+
+    result
+}
+
+fn around_chain<M>(
+    chain: &[&dyn Middleware<M>],
+    ctx: &rm::Context,
+    module: &M,
+    command: &str,
+    next: &mut dyn FnMut() -> rm::RedisResult,
+) -> rm::RedisResult {
+    match chain.split_first() {
+        Some((first, rest)) => first.around(ctx, module, command, &mut || {
+            around_chain(rest, ctx, module, command, next)
+        }),
+        None => next(),
+    }
+}
+
+macro_rules! tuple {
+    ( $($name:ident $index:tt,)+ ) => (
+        impl<M, $($name, )*> Middleware<M> for ($($name, )*)
+        where
+            $( $name: Middleware<M>, )*
+        {
+            fn before(
+                &self,
+                ctx: &rm::Context,
+                module: &M,
+                command: &str,
+                request: &dyn Any,
+            ) -> Result<(), rm::RedisError> {
+                $( self.$index.before(ctx, module, command, request)?; )*
+
+                Ok(())
+            }
+
+            fn around(
+                &self,
+                ctx: &rm::Context,
+                module: &M,
+                command: &str,
+                next: &mut dyn FnMut() -> rm::RedisResult,
+            ) -> rm::RedisResult {
+                let chain: &[&dyn Middleware<M>] = &[$( &self.$index, )*];
+
+                around_chain(chain, ctx, module, command, next)
+            }
+
+            fn after(
+                &self,
+                ctx: &rm::Context,
+                module: &M,
+                command: &str,
+                result: &mut rm::RedisResult,
+            ) {
+                let chain: &[&dyn Middleware<M>] = &[$( &self.$index, )*];
+
+                for middleware in chain.iter().rev() {
+                    middleware.after(ctx, module, command, result);
+                }
+            }
+        }
+    )
+}
+
+tuple![A 0,];
+tuple![A 0, B 1,];
+tuple![A 0, B 1, C 2,];
+tuple![A 0, B 1, C 2, D 3,];
+tuple![A 0, B 1, C 2, D 3, E 4,];
+tuple![A 0, B 1, C 2, D 3, E 4, F 5,];
+tuple![A 0, B 1, C 2, D 3, E 4, F 5, G 6,];
+tuple![A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7,];
+
+#[cfg(test)]
+mod tests {
+    use std::{any::Any, cell::RefCell, ptr};
+
+    use redis_module as rm;
+
+    use super::{wrap, Middleware};
+
+    struct Trace(&'static str);
+
+    impl Middleware<RefCell<Vec<String>>> for Trace {
+        fn before(
+            &self,
+            _ctx: &rm::Context,
+            log: &RefCell<Vec<String>>,
+            command: &str,
+            _request: &dyn Any,
+        ) -> Result<(), rm::RedisError> {
+            log.borrow_mut()
+                .push(format!("{} before {}", self.0, command));
+
+            Ok(())
+        }
+
+        fn around(
+            &self,
+            _ctx: &rm::Context,
+            log: &RefCell<Vec<String>>,
+            _command: &str,
+            next: &mut dyn FnMut() -> rm::RedisResult,
+        ) -> rm::RedisResult {
+            log.borrow_mut().push(format!("{} around", self.0));
+
+            next()
+        }
+
+        fn after(
+            &self,
+            _ctx: &rm::Context,
+            log: &RefCell<Vec<String>>,
+            _command: &str,
+            _result: &mut rm::RedisResult,
+        ) {
+            log.borrow_mut().push(format!("{} after", self.0));
+        }
+    }
+
+    #[test]
+    fn chain_order() {
+        let ctx = rm::Context::new(ptr::null_mut());
+        let log = RefCell::new(Vec::new());
+
+        let chain = (Trace("a"), Trace("b"));
+
+        chain.before(&ctx, &log, "get", &()).unwrap();
+
+        let result = wrap(&chain, &ctx, &log, "get", || {
+            log.borrow_mut().push("handle".to_string());
+
+            Ok(rm::RedisValue::Integer(1))
+        });
+
+        assert!(matches!(result, Ok(rm::RedisValue::Integer(1))));
+        assert_eq!(
+            log.into_inner(),
+            [
+                "a before get",
+                "b before get",
+                "a around",
+                "b around",
+                "handle",
+                "b after",
+                "a after",
+            ]
+        );
+    }
+}
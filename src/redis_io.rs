@@ -1,12 +1,16 @@
 use redis_module as rm;
 
 pub trait Loader {
+    type String;
+    type Buffer: AsRef<[u8]>;
+
     fn double(&self) -> Result<f64, rm::error::Error>;
     fn float(&self) -> Result<f32, rm::error::Error>;
     fn unsigned(&self) -> Result<u64, rm::error::Error>;
     fn signed(&self) -> Result<i64, rm::error::Error>;
-    fn string(&self) -> Result<rm::RedisString, rm::error::Error>;
-    fn buffer(&self) -> Result<rm::RedisBuffer, rm::error::Error>;
+    fn string(&self) -> Result<Self::String, rm::error::Error>;
+    /// Reads a string or a buffer, both are written as the same rdb string.
+    fn buffer(&self) -> Result<Self::Buffer, rm::error::Error>;
 }
 
 pub trait Saver {
@@ -23,6 +27,9 @@ pub struct IOLoader {
 }
 
 impl Loader for IOLoader {
+    type String = rm::RedisString;
+    type Buffer = rm::RedisBuffer;
+
     fn double(&self) -> Result<f64, rm::error::Error> {
         rm::load_double(self.rdb)
     }
@@ -35,10 +42,10 @@ impl Loader for IOLoader {
     fn signed(&self) -> Result<i64, rm::error::Error> {
         rm::load_signed(self.rdb)
     }
-    fn string(&self) -> Result<rm::RedisString, rm::error::Error> {
+    fn string(&self) -> Result<Self::String, rm::error::Error> {
         rm::load_string(self.rdb)
     }
-    fn buffer(&self) -> Result<rm::RedisBuffer, rm::error::Error> {
+    fn buffer(&self) -> Result<Self::Buffer, rm::error::Error> {
         rm::load_string_buffer(self.rdb)
     }
 }
@@ -67,3 +74,111 @@ impl Saver for IOSaver {
         rm::save_slice(self.rdb, val.as_ref())
     }
 }
+
+/// In memory rdb for the tests.
+#[cfg(test)]
+pub(crate) mod memory {
+    use std::{cell::RefCell, collections::VecDeque};
+
+    use redis_module as rm;
+
+    use super::{Loader, Saver};
+
+    /// Values written by a [`Saver`] and read back in order by its [`Loader`].
+    #[derive(Default)]
+    pub(crate) struct MemoryIO {
+        values: RefCell<VecDeque<Value>>,
+    }
+
+    #[derive(Debug)]
+    enum Value {
+        Double(f64),
+        Float(f32),
+        Unsigned(u64),
+        Signed(i64),
+        /// Strings and buffers are the same rdb string.
+        Bytes(Vec<u8>),
+    }
+
+    impl MemoryIO {
+        pub(crate) fn is_empty(&self) -> bool {
+            self.values.borrow().is_empty()
+        }
+
+        fn push(&self, value: Value) {
+            self.values.borrow_mut().push_back(value);
+        }
+
+        fn pop(&self) -> Result<Value, rm::error::Error> {
+            self.values
+                .borrow_mut()
+                .pop_front()
+                .ok_or_else(|| rm::error::Error::generic("nothing left to load"))
+        }
+    }
+
+    fn unexpected(value: Value) -> rm::error::Error {
+        rm::error::Error::generic(&format!("unexpected {:?}", value))
+    }
+
+    impl Saver for MemoryIO {
+        fn double(&self, val: f64) {
+            self.push(Value::Double(val))
+        }
+        fn float(&self, val: f32) {
+            self.push(Value::Float(val))
+        }
+        fn unsigned(&self, val: u64) {
+            self.push(Value::Unsigned(val))
+        }
+        fn signed(&self, val: i64) {
+            self.push(Value::Signed(val))
+        }
+        fn string<S: AsRef<str>>(&self, val: S) {
+            self.push(Value::Bytes(val.as_ref().as_bytes().to_vec()))
+        }
+        fn buffer<S: AsRef<[u8]>>(&self, val: S) {
+            self.push(Value::Bytes(val.as_ref().to_vec()))
+        }
+    }
+
+    impl Loader for MemoryIO {
+        type String = String;
+        type Buffer = Vec<u8>;
+
+        fn double(&self) -> Result<f64, rm::error::Error> {
+            match self.pop()? {
+                Value::Double(val) => Ok(val),
+                value => Err(unexpected(value)),
+            }
+        }
+        fn float(&self) -> Result<f32, rm::error::Error> {
+            match self.pop()? {
+                Value::Float(val) => Ok(val),
+                value => Err(unexpected(value)),
+            }
+        }
+        fn unsigned(&self) -> Result<u64, rm::error::Error> {
+            match self.pop()? {
+                Value::Unsigned(val) => Ok(val),
+                value => Err(unexpected(value)),
+            }
+        }
+        fn signed(&self) -> Result<i64, rm::error::Error> {
+            match self.pop()? {
+                Value::Signed(val) => Ok(val),
+                value => Err(unexpected(value)),
+            }
+        }
+        fn string(&self) -> Result<Self::String, rm::error::Error> {
+            String::from_utf8(self.buffer()?)
+                .map_err(|err| rm::error::Error::generic(&err.to_string()))
+        }
+        fn buffer(&self) -> Result<Self::Buffer, rm::error::Error> {
+            match self.pop()? {
+                Value::Bytes(val) => Ok(val),
+                value => Err(unexpected(value)),
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use redis_module as rm;
+
+/// Error replied as `-<CODE> <message>`, the message is the `Display` output.
+pub trait IntoRedisError: fmt::Display {
+    /// First word of the error reply, e.g. `ERR`, `WRONGTYPE` or a module specific one.
+    fn code(&self) -> &str {
+        "ERR"
+    }
+
+    fn into_redis_error(self) -> rm::RedisError
+    where
+        Self: Sized,
+    {
+        rm::RedisError::String(format!("{} {}", self.code(), self))
+    }
+}
+
+/// Already a reply, kept as is.
+impl IntoRedisError for rm::RedisError {
+    fn into_redis_error(self) -> rm::RedisError {
+        self
+    }
+}
+
+/// Result of a `RequestHandler`, replied to the client.
+pub trait IntoReply {
+    fn into_reply(self) -> rm::RedisResult;
+}
+
+impl<V, E> IntoReply for Result<V, E>
+where
+    V: Into<rm::RedisValue>,
+    E: IntoRedisError,
+{
+    fn into_reply(self) -> rm::RedisResult {
+        self.map(Into::into)
+            .map_err(IntoRedisError::into_redis_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_module as rm;
+
+    use super::{IntoRedisError, IntoReply};
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("task {0} is locked")]
+    struct Locked(u64);
+
+    impl IntoRedisError for Locked {
+        fn code(&self) -> &str {
+            "LOCKED"
+        }
+    }
+
+    #[test]
+    fn codes() {
+        let err = match Err::<rm::RedisValue, _>(Locked(7)).into_reply() {
+            Err(rm::RedisError::String(err)) => err,
+            _ => panic!("not a string error"),
+        };
+
+        assert_eq!(err, "LOCKED task 7 is locked");
+    }
+}
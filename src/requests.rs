@@ -1,16 +1,21 @@
-use std::{ffi, ffi::CString};
+use std::{any::Any, ffi, ffi::CString};
 
 use redis_module as rm;
 
-use crate::{InstanceMngr, Module};
+use crate::{guard, middleware, IntoReply, InstanceMngr, Module};
 
-pub trait Command: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> {
+/// Request parsed from the command arguments, usually with `#[derive(Command)]`.
+pub trait Command: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> + 'static {
+    /// Checks the parsed request before it is handled, the error is replied.
     fn validate(&self) -> Result<(), rm::RedisError> {
         Ok(())
     }
-}
 
-impl<R> Command for R where R: TryFrom<Vec<rm::RedisString>, Error = rm::RedisError> {}
+    /// Request passed to `Middleware::before`, the inner request of `Async` and `Blocking`.
+    fn request(&self) -> &dyn Any {
+        self
+    }
+}
 
 pub struct CommandKeys {
     pub first: u8,
@@ -33,7 +38,10 @@ pub trait RequestHandler<R: Command> {
     const FLAGS: &'static str;
     const KEYS: CommandKeys;
 
-    type Result: Into<rm::RedisResult>;
+    /// `rm::RedisResult` or any `Result` with an [`IntoRedisError`] error.
+    ///
+    /// [`IntoRedisError`]: crate::IntoRedisError
+    type Result: IntoReply;
 
     fn handle(&self, ctx: &rm::Context, req: R) -> Self::Result;
 }
@@ -98,6 +106,26 @@ where
     M: RequestHandler<C>,
 {
     let ctx = &rm::Context::new(ctx);
+    let command = <M as RequestHandler<C>>::NAME;
+
+    match guard::catch(command, || run::<M, C, G>(ctx, argv, argc)) {
+        Some(status) => status,
+        None => ctx.reply(guard::panicked(command)) as ffi::c_int,
+    }
+}
+
+fn run<M, C, G>(
+    ctx: &rm::Context,
+    argv: *mut *mut rm::RedisModuleString,
+    argc: ffi::c_int,
+) -> ffi::c_int
+where
+    M: 'static,
+    M: Module,
+    C: Command,
+    G: InstanceMngr<M>,
+    M: RequestHandler<C>,
+{
     let args = rm::decode_args(ctx.ctx, argv, argc);
 
     let instance = match G::get() {
@@ -110,9 +138,26 @@ where
         Err(err) => return ctx.reply(Err(err)) as ffi::c_int,
     };
 
-    let result = instance.handle(ctx, req);
+    if let Err(err) = req.validate() {
+        return ctx.reply(Err(err)) as ffi::c_int;
+    }
+
+    let middleware = match instance.middleware() {
+        Some(middleware) => middleware,
+        None => return ctx.reply(instance.handle(ctx, req).into_reply()) as ffi::c_int,
+    };
+
+    let command = <M as RequestHandler<C>>::NAME;
+
+    if let Err(err) = middleware.before(ctx, instance, command, req.request()) {
+        return ctx.reply(Err(err)) as ffi::c_int;
+    }
+
+    let result = middleware::wrap(middleware, ctx, instance, command, || {
+        instance.handle(ctx, req).into_reply()
+    });
 
-    ctx.reply(result.into()) as ffi::c_int
+    ctx.reply(result) as ffi::c_int
 }
 
 // adapted from core/src/fmt/mod.rs tuple
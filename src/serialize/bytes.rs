@@ -0,0 +1,144 @@
+use std::cell::{Cell, RefCell};
+
+use redis_module as rm;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Loader, Saver};
+
+use super::{from_loader, to_saver_checked, Error};
+
+/// Writes `value` in the rdb format into a buffer, for values sent outside an rdb.
+///
+/// Numbers are little endian, strings and buffers are prefixed with their length.
+pub(crate) fn to_bytes<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    let saver = BytesSaver::default();
+
+    to_saver_checked(value, &saver)?;
+
+    Ok(saver.bytes.into_inner())
+}
+
+/// Reads a value written by [`to_bytes`], trailing bytes are an error.
+pub(crate) fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    let loader = BytesLoader {
+        bytes,
+        offset: Cell::new(0),
+    };
+
+    let value = from_loader(&loader)?;
+
+    if loader.offset.get() != bytes.len() {
+        return Err(Error::Custom("trailing bytes".to_string()));
+    }
+
+    Ok(value)
+}
+
+#[derive(Default)]
+struct BytesSaver {
+    bytes: RefCell<Vec<u8>>,
+}
+
+impl Saver for BytesSaver {
+    fn double(&self, val: f64) {
+        self.bytes.borrow_mut().extend(val.to_le_bytes())
+    }
+    fn float(&self, val: f32) {
+        self.bytes.borrow_mut().extend(val.to_le_bytes())
+    }
+    fn unsigned(&self, val: u64) {
+        self.bytes.borrow_mut().extend(val.to_le_bytes())
+    }
+    fn signed(&self, val: i64) {
+        self.bytes.borrow_mut().extend(val.to_le_bytes())
+    }
+    fn string<S: AsRef<str>>(&self, val: S) {
+        self.buffer(val.as_ref())
+    }
+    fn buffer<S: AsRef<[u8]>>(&self, val: S) {
+        let val = val.as_ref();
+
+        self.unsigned(val.len() as u64);
+        self.bytes.borrow_mut().extend_from_slice(val)
+    }
+}
+
+struct BytesLoader<'b> {
+    bytes: &'b [u8],
+    offset: Cell<usize>,
+}
+
+impl<'b> BytesLoader<'b> {
+    fn take<const N: usize>(&self) -> Result<[u8; N], rm::error::Error> {
+        let mut taken = [0; N];
+
+        taken.copy_from_slice(self.slice(N)?);
+
+        Ok(taken)
+    }
+
+    fn slice(&self, len: usize) -> Result<&'b [u8], rm::error::Error> {
+        let start = self.offset.get();
+
+        let slice = start
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or_else(|| rm::error::Error::generic("unexpected end of bytes"))?;
+
+        self.offset.set(start + len);
+
+        Ok(slice)
+    }
+}
+
+impl<'b> Loader for BytesLoader<'b> {
+    type String = String;
+    type Buffer = Vec<u8>;
+
+    fn double(&self) -> Result<f64, rm::error::Error> {
+        self.take().map(f64::from_le_bytes)
+    }
+    fn float(&self) -> Result<f32, rm::error::Error> {
+        self.take().map(f32::from_le_bytes)
+    }
+    fn unsigned(&self) -> Result<u64, rm::error::Error> {
+        self.take().map(u64::from_le_bytes)
+    }
+    fn signed(&self) -> Result<i64, rm::error::Error> {
+        self.take().map(i64::from_le_bytes)
+    }
+    fn string(&self) -> Result<Self::String, rm::error::Error> {
+        String::from_utf8(self.buffer()?).map_err(|err| rm::error::Error::generic(&err.to_string()))
+    }
+    fn buffer(&self) -> Result<Self::Buffer, rm::error::Error> {
+        let len = usize::try_from(self.unsigned()?)
+            .map_err(|err| rm::error::Error::generic(&err.to_string()))?;
+
+        Ok(self.slice(len)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes, to_bytes};
+
+    #[test]
+    fn round_trips() {
+        let value = (1u8, -2i64, 0.5f64, "task".to_string(), Some(vec![3u16, 4]));
+
+        let bytes = to_bytes(&value).unwrap();
+
+        assert_eq!(
+            from_bytes::<(u8, i64, f64, String, Option<Vec<u16>>)>(&bytes).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing() {
+        let bytes = to_bytes(&(7u64, "task")).unwrap();
+
+        assert!(from_bytes::<(u64, String)>(&bytes[..bytes.len() - 1]).is_err());
+        assert!(from_bytes::<u64>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,474 @@
+use serde::de::{self, IntoDeserializer};
+
+use crate::Loader;
+
+use super::Error;
+
+/// Serde deserializer on top of a [`Loader`].
+///
+/// The format is not self-describing, so `deserialize_any` is not supported.
+pub struct Deserializer<'l, L: Loader> {
+    loader: &'l L,
+}
+
+impl<'l, L: Loader> Deserializer<'l, L> {
+    pub fn new(loader: &'l L) -> Self {
+        Self { loader }
+    }
+
+    fn tag(&self) -> Result<u64, Error> {
+        Ok(self.loader.unsigned()?)
+    }
+
+    fn length(&self) -> Result<usize, Error> {
+        let len = self.loader.unsigned()?;
+
+        usize::try_from(len).map_err(|_| Error::InvalidLength(len))
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.loader.buffer()?.as_ref().to_vec())
+    }
+
+    fn string(&self) -> Result<String, Error> {
+        String::from_utf8(self.bytes()?).map_err(Error::InvalidString)
+    }
+}
+
+impl<'de, 'a, 'l, L: Loader> de::Deserializer<'de> for &'a mut Deserializer<'l, L> {
+    type Error = Error;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::AnyNotSupported)
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.tag()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    // serde integer visitors accept any width and check the range themselves
+    fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.loader.signed()?)
+    }
+
+    fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.loader.unsigned()?)
+    }
+
+    fn deserialize_f32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(self.loader.float()?)
+    }
+
+    fn deserialize_f64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(self.loader.double()?)
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let code = self.tag()?;
+
+        let v = u32::try_from(code)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(Error::InvalidChar(code))?;
+
+        visitor.visit_char(v)
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.tag()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            tag => Err(Error::InvalidTag(tag)),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let len = self.length()?;
+
+        visitor.visit_seq(Access { de: self, len })
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(Access { de: self, len })
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let len = self.length()?;
+
+        visitor.visit_map(Access { de: self, len })
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error::AnyNotSupported)
+    }
+}
+
+impl<'de, 'a, 'l, L: Loader> de::EnumAccess<'de> for &'a mut Deserializer<'l, L> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let tag = self.tag()?;
+        let index = u32::try_from(tag).map_err(|_| Error::InvalidTag(tag))?;
+
+        let deserializer: de::value::U32Deserializer<Error> = index.into_deserializer();
+
+        Ok((seed.deserialize(deserializer)?, self))
+    }
+}
+
+impl<'de, 'a, 'l, L: Loader> de::VariantAccess<'de> for &'a mut Deserializer<'l, L> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+/// Sequence and map access with a known number of elements.
+struct Access<'a, 'l, L: Loader> {
+    de: &'a mut Deserializer<'l, L>,
+    len: usize,
+}
+
+impl<'de, 'a, 'l, L: Loader> de::SeqAccess<'de> for Access<'a, 'l, L> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.len == 0 {
+            return Ok(None);
+        }
+
+        self.len -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de, 'a, 'l, L: Loader> de::MapAccess<'de> for Access<'a, 'l, L> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.len == 0 {
+            return Ok(None);
+        }
+
+        self.len -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fmt};
+
+    use serde::{de, Deserialize, Serialize};
+
+    use crate::{
+        from_loader,
+        from_loader_marked,
+        redis_io::memory::MemoryIO,
+        to_saver,
+        to_saver_marked,
+        Saver,
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum State {
+        Pending,
+        Retry(u32),
+        Moved(String, u8),
+        Failed { reason: String },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Id(u64);
+
+    /// Written with `serialize_bytes`, like `serde_bytes`.
+    #[derive(Debug, PartialEq)]
+    struct Bytes(Vec<u8>);
+
+    impl Serialize for Bytes {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Bytes {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct Visitor;
+
+            impl<'de> de::Visitor<'de> for Visitor {
+                type Value = Bytes;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("bytes")
+                }
+
+                fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                    Ok(Bytes(v))
+                }
+            }
+
+            deserializer.deserialize_byte_buf(Visitor)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Task {
+        id: Id,
+        r#type: String,
+        payload: Bytes,
+        retries: i8,
+        weight: f32,
+        score: f64,
+        done: bool,
+        grade: char,
+        worker: Option<String>,
+        parent: Option<Id>,
+        labels: BTreeMap<String, Vec<u16>>,
+        states: Vec<State>,
+        span: (i64, u64),
+        marker: (),
+    }
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + de::DeserializeOwned,
+    {
+        let io = MemoryIO::default();
+
+        to_saver(value, &io).expect("saved");
+
+        let loaded = from_loader(&io).expect("loaded");
+
+        assert!(io.is_empty(), "every value is read back");
+
+        loaded
+    }
+
+    #[test]
+    fn round_trips() {
+        let task = Task {
+            id: Id(7),
+            r#type: "test".to_string(),
+            payload: Bytes(vec![0, 159, 146, 150]),
+            retries: -1,
+            weight: 0.5,
+            score: -1.25,
+            done: true,
+            grade: 'λ',
+            worker: Some("w1".to_string()),
+            parent: None,
+            labels: BTreeMap::from([("a".to_string(), vec![1, 2]), ("b".to_string(), vec![])]),
+            states: vec![
+                State::Pending,
+                State::Retry(3),
+                State::Moved("w2".to_string(), 1),
+                State::Failed {
+                    reason: "timeout".to_string(),
+                },
+            ],
+            span: (i64::MIN, u64::MAX),
+            marker: (),
+        };
+
+        assert_eq!(round_trip(&task), task);
+        assert_eq!(round_trip(&String::new()), "");
+        assert_eq!(round_trip(&Some(Some(false))), Some(Some(false)));
+    }
+
+    #[test]
+    fn invalid() {
+        let io = MemoryIO::default();
+
+        io.unsigned(2);
+        assert!(from_loader::<bool, _>(&io).is_err());
+
+        io.unsigned(4);
+        assert!(from_loader::<State, _>(&io).is_err());
+
+        io.unsigned(1);
+        assert!(from_loader::<String, _>(&io).is_err());
+
+        io.signed(300);
+        assert!(from_loader::<i8, _>(&io).is_err());
+
+        io.buffer([0xff]);
+        assert!(from_loader::<String, _>(&io).is_err());
+
+        assert!(from_loader::<u64, _>(&io).is_err());
+    }
+
+    #[test]
+    fn marked() {
+        struct Unserializable;
+
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("unserializable"))
+            }
+        }
+
+        let io = MemoryIO::default();
+
+        to_saver_marked(&(7u8, "task"), &io).unwrap();
+        assert!(to_saver_marked(&Unserializable, &io).is_err());
+        to_saver_marked(&8u8, &io).unwrap();
+
+        assert_eq!(
+            from_loader_marked::<(u8, String), _>(&io).unwrap(),
+            (7, "task".to_string())
+        );
+        assert!(from_loader_marked::<u8, _>(&io).is_err());
+        assert_eq!(from_loader_marked::<u8, _>(&io).unwrap(), 8);
+        assert!(io.is_empty());
+    }
+}
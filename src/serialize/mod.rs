@@ -0,0 +1,135 @@
+mod bytes;
+mod de;
+mod ser;
+
+use std::fmt::Display;
+
+use redis_module as rm;
+use serde::{de::DeserializeOwned, Serialize};
+
+pub(crate) use bytes::{from_bytes, to_bytes};
+pub use de::Deserializer;
+pub use ser::Serializer;
+
+use crate::{Loader, Saver};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Custom(String),
+    #[error("sequence length must be known upfront")]
+    UnknownLength,
+    #[error("length {0} does not fit into usize")]
+    InvalidLength(u64),
+    #[error("invalid tag {0}")]
+    InvalidTag(u64),
+    #[error("invalid char {0}")]
+    InvalidChar(u64),
+    #[error("invalid utf8 string: {0}")]
+    InvalidString(std::string::FromUtf8Error),
+    #[error("format is not self-describing")]
+    AnyNotSupported,
+    #[error("redis error: {0}")]
+    Redis(#[from] rm::error::Error),
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl From<Error> for rm::error::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Redis(err) => err,
+            err => rm::error::Error::generic(&err.to_string()),
+        }
+    }
+}
+
+/// Writes `value` to the rdb.
+///
+/// Sequences and maps are prefixed with their length, enum variants with
+/// their index, options with a `0`/`1` tag. Struct field names are not
+/// written, so fields must be read back in declaration order.
+pub fn to_saver<T, S>(value: &T, saver: &S) -> Result<(), Error>
+where
+    T: ?Sized + Serialize,
+    S: Saver,
+{
+    value.serialize(&mut Serializer::new(saver))
+}
+
+/// Writes `value` like [`to_saver`], once it is known to serialize.
+///
+/// A value failing half way leaves a truncated rdb, read from the wrong offset by the next
+/// keys. The value is first serialized without writing, its error is returned before writing.
+pub fn to_saver_checked<T, S>(value: &T, saver: &S) -> Result<(), Error>
+where
+    T: ?Sized + Serialize,
+    S: Saver,
+{
+    to_saver(value, &Discard)?;
+    to_saver(value, saver)
+}
+
+/// Writes `value` like [`to_saver_checked`] behind a `1` tag, for [`from_loader_marked`].
+///
+/// A value failing to serialize writes a lone `0` marker instead, keeping the next keys
+/// readable, and its error is returned.
+pub fn to_saver_marked<T, S>(value: &T, saver: &S) -> Result<(), Error>
+where
+    T: ?Sized + Serialize,
+    S: Saver,
+{
+    if let Err(err) = to_saver(value, &Discard) {
+        saver.unsigned(0);
+        return Err(err);
+    }
+
+    saver.unsigned(1);
+    to_saver(value, saver)
+}
+
+/// Saver writing nothing, for [`to_saver_checked`].
+struct Discard;
+
+impl Saver for Discard {
+    fn double(&self, _val: f64) {}
+    fn float(&self, _val: f32) {}
+    fn unsigned(&self, _val: u64) {}
+    fn signed(&self, _val: i64) {}
+    fn string<S: AsRef<str>>(&self, _val: S) {}
+    fn buffer<S: AsRef<[u8]>>(&self, _val: S) {}
+}
+
+/// Reads a value written by [`to_saver`].
+pub fn from_loader<T, L>(loader: &L) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    L: Loader,
+{
+    T::deserialize(&mut Deserializer::new(loader))
+}
+
+/// Reads a value written by [`to_saver_marked`], the marker of a failed save is an error.
+pub fn from_loader_marked<T, L>(loader: &L) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    L: Loader,
+{
+    match loader.unsigned()? {
+        1 => from_loader(loader),
+        0 => Err(Error::Custom(
+            "value failed to serialize when saved".to_string(),
+        )),
+        tag => Err(Error::InvalidTag(tag)),
+    }
+}
@@ -0,0 +1,470 @@
+use serde::{ser, Serialize};
+
+use crate::Saver;
+
+use super::Error;
+
+/// Serde serializer on top of a [`Saver`].
+pub struct Serializer<'s, S: Saver> {
+    saver: &'s S,
+}
+
+impl<'s, S: Saver> Serializer<'s, S> {
+    pub fn new(saver: &'s S) -> Self {
+        Self { saver }
+    }
+
+    fn tag(&self, tag: u64) {
+        self.saver.unsigned(tag)
+    }
+
+    fn length(&self, len: Option<usize>) -> Result<(), Error> {
+        let len = len.ok_or(Error::UnknownLength)?;
+
+        self.saver.unsigned(len as u64);
+
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::Serializer for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.saver.unsigned(v as u64);
+
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.saver.signed(v);
+
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.saver.unsigned(v);
+
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.saver.float(v);
+
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.saver.double(v);
+
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_u32(u32::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.saver.string(v);
+
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.saver.buffer(v);
+
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.tag(0);
+
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.tag(1);
+
+        value.serialize(self)
+    }
+
+    // unit carries no data, so nothing is written
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        self.tag(variant_index.into());
+
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.tag(variant_index.into());
+
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        self.length(len)?;
+
+        Ok(self)
+    }
+
+    // tuple length is known to the deserializer, so it is not written
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        self.tag(variant_index.into());
+
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        self.length(len)?;
+
+        Ok(self)
+    }
+
+    // fields are written in declaration order without names
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        self.tag(variant_index.into());
+
+        Ok(self)
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeSeq for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeTuple for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeTupleStruct for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeTupleVariant for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeMap for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeStruct for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 's, S: Saver> ser::SerializeStructVariant for &'a mut Serializer<'s, S> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    use serde::Serialize;
+
+    use crate::{to_saver, Saver};
+
+    #[derive(Default)]
+    struct DummySaver {
+        written: RefCell<Vec<String>>,
+    }
+
+    impl DummySaver {
+        fn push(&self, val: String) {
+            self.written.borrow_mut().push(val);
+        }
+    }
+
+    impl Saver for DummySaver {
+        fn double(&self, val: f64) {
+            self.push(format!("double: {:?}", val));
+        }
+
+        fn float(&self, val: f32) {
+            self.push(format!("float: {:?}", val));
+        }
+
+        fn unsigned(&self, val: u64) {
+            self.push(format!("unsigned: {:?}", val));
+        }
+
+        fn signed(&self, val: i64) {
+            self.push(format!("signed: {:?}", val));
+        }
+
+        fn string<S: AsRef<str>>(&self, val: S) {
+            self.push(format!("string: {:?}", val.as_ref()));
+        }
+
+        fn buffer<S: AsRef<[u8]>>(&self, val: S) {
+            self.push(format!("buffer: {:?}", val.as_ref()));
+        }
+    }
+
+    #[derive(Serialize)]
+    enum State {
+        Pending,
+        Retry(u32),
+        Failed { reason: String },
+    }
+
+    #[derive(Serialize)]
+    struct Task {
+        r#type: String,
+        retries: i64,
+        weight: f32,
+        workers: Vec<String>,
+        labels: BTreeMap<String, u8>,
+        timer_id: Option<u64>,
+        states: (State, State, State),
+    }
+
+    #[test]
+    fn works() {
+        let saver = DummySaver::default();
+
+        let task = Task {
+            r#type: "test".to_string(),
+            retries: -1,
+            weight: 0.5,
+            workers: vec!["w1".to_string(), "w2".to_string()],
+            labels: BTreeMap::from([("a".to_string(), 1)]),
+            timer_id: None,
+            states: (
+                State::Pending,
+                State::Retry(3),
+                State::Failed {
+                    reason: "timeout".to_string(),
+                },
+            ),
+        };
+
+        to_saver(&task, &saver).expect("ok");
+
+        assert_eq!(
+            saver.written.into_inner(),
+            vec![
+                r#"string: "test""#,
+                "signed: -1",
+                "float: 0.5",
+                "unsigned: 2",
+                r#"string: "w1""#,
+                r#"string: "w2""#,
+                "unsigned: 1",
+                r#"string: "a""#,
+                "unsigned: 1",
+                "unsigned: 0",
+                "unsigned: 0",
+                "unsigned: 1",
+                "unsigned: 3",
+                "unsigned: 2",
+                r#"string: "timeout""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_length() {
+        struct Unsized;
+
+        impl Serialize for Unsized {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(std::iter::from_fn(|| Some(1)).take(2).filter(|_| true))
+            }
+        }
+
+        assert!(to_saver(&Unsized, &DummySaver::default()).is_err());
+    }
+}
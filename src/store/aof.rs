@@ -0,0 +1,55 @@
+use std::{
+    ffi::{self, CString},
+    ptr,
+};
+
+use redis_module as rm;
+
+/// Emits the commands that rebuild a value on AOF rewrite.
+pub struct AofEmitter {
+    pub(crate) io: *mut rm::RedisModuleIO,
+}
+
+impl AofEmitter {
+    /// Appends `command args...` to the rewritten AOF.
+    pub fn emit<A: AsRef<[u8]>>(&self, command: &str, args: &[A]) {
+        let command = match CString::new(command) {
+            Ok(command) => command,
+            Err(err) => {
+                log::error!(target: "aof", "invalid command name {:?}: {}", command, err);
+
+                return;
+            }
+        };
+
+        let mut argv: Vec<*mut rm::RedisModuleString> = args
+            .iter()
+            .map(|arg| {
+                let arg = arg.as_ref();
+
+                unsafe {
+                    rm::raw::RedisModule_CreateString.unwrap()(
+                        ptr::null_mut(),
+                        arg.as_ptr().cast::<ffi::c_char>(),
+                        arg.len(),
+                    )
+                }
+            })
+            .collect();
+
+        // "v" is a vector of strings followed by its length
+        unsafe {
+            rm::raw::RedisModule_EmitAOF.unwrap()(
+                self.io,
+                command.as_ptr(),
+                b"v\0".as_ptr().cast::<ffi::c_char>(),
+                argv.as_mut_ptr(),
+                argv.len(),
+            );
+        }
+
+        for arg in argv {
+            unsafe { rm::raw::RedisModule_FreeString.unwrap()(ptr::null_mut(), arg) };
+        }
+    }
+}
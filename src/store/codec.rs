@@ -0,0 +1,347 @@
+use std::{marker::PhantomData, str};
+
+use crate::Type;
+
+/// Id of a [`Type`], written as bytes into its key and the index members.
+///
+/// Strings and integers are written as text, byte vectors and arrays as is.
+pub trait KeyId: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    /// Id written by `to_bytes`, `None` for bytes of another id.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl KeyId for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl KeyId for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl<const N: usize> KeyId for [u8; N] {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+macro_rules! key_id_int {
+    ($($ty:ty),*) => {
+        $(
+            impl KeyId for $ty {
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_string().into_bytes()
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    str::from_utf8(bytes).ok()?.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+key_id_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// Maps ids of `T` to redis keys and back.
+///
+/// `decode` must accept every key produced by `encode` and reject keys of other types.
+pub trait KeyCodec<T: Type>: Send + Sync {
+    fn encode(&self, id: &T::IDType) -> Vec<u8>;
+    fn decode(&self, key: &[u8]) -> Option<T::IDType>;
+
+    /// Key of the index `name`, tag indexes append `:<value>` to it.
+    ///
+    /// `<name>:<prefix>#idx:<name>`, the `#` keeps index keys apart from the keys of any id.
+    fn index_key(&self, name: &str) -> String {
+        format!("{}:{}#idx:{}", T::NAME, T::PREFIX, name)
+    }
+}
+
+/// `<name>:<prefix>:<id>`, the key layout used when no codec is set.
+pub struct DefaultCodec;
+
+impl<T: Type> KeyCodec<T> for DefaultCodec {
+    fn encode(&self, id: &T::IDType) -> Vec<u8> {
+        let mut key = format!("{}:{}:", T::NAME, T::PREFIX).into_bytes();
+
+        key.extend(id.to_bytes());
+        key
+    }
+
+    fn decode(&self, key: &[u8]) -> Option<T::IDType> {
+        KeyId::from_bytes(strip_type::<T>(key)?)
+    }
+}
+
+/// `<namespace>:<name>:<prefix>:<id>`, e.g. a namespace taken from the module config.
+pub struct Namespaced {
+    namespace: String,
+}
+
+impl Namespaced {
+    pub fn new<N: Into<String>>(namespace: N) -> Self {
+        Self {
+            namespace: namespace.into(),
+        }
+    }
+}
+
+impl<T: Type> KeyCodec<T> for Namespaced {
+    fn encode(&self, id: &T::IDType) -> Vec<u8> {
+        namespaced(&self.namespace, KeyCodec::<T>::encode(&DefaultCodec, id))
+    }
+
+    fn decode(&self, key: &[u8]) -> Option<T::IDType> {
+        let key = key
+            .strip_prefix(self.namespace.as_bytes())?
+            .strip_prefix(b":")?;
+
+        KeyCodec::<T>::decode(&DefaultCodec, key)
+    }
+
+    fn index_key(&self, name: &str) -> String {
+        format!(
+            "{}:{}",
+            self.namespace,
+            KeyCodec::<T>::index_key(&DefaultCodec, name)
+        )
+    }
+}
+
+/// `[<namespace>:]<name>:<prefix>:{<tag>}:<id>`, keys with the same tag share a cluster slot.
+///
+/// Index keys are tagged only by [`HashTag::fixed`], otherwise their values span slots and
+/// the indexes can only be used outside of a cluster.
+///
+/// ```ignore
+/// // every task of a worker lands in the slot of the worker
+/// let codec = HashTag::<Task>::new(|id| worker_of(id));
+/// ```
+pub struct HashTag<T: Type> {
+    namespace: Option<String>,
+    tag: Box<dyn Fn(&T::IDType) -> Vec<u8> + Send + Sync>,
+    index_tag: Option<String>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Type> HashTag<T> {
+    pub fn new<F, S>(tag: F) -> Self
+    where
+        F: Fn(&T::IDType) -> S + Send + Sync + 'static,
+        S: Into<Vec<u8>>,
+    {
+        Self {
+            namespace: None,
+            tag: Box::new(move |id| tag(id).into()),
+            index_tag: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Tags every key with its own id, so keys derived from the same id are colocated.
+    pub fn id() -> Self {
+        Self::new(<T::IDType as KeyId>::to_bytes)
+    }
+
+    /// Tags every key with `tag`, all values of `T` share one slot.
+    pub fn fixed<S: Into<String>>(tag: S) -> Self {
+        let tag = tag.into();
+
+        Self {
+            index_tag: Some(tag.clone()),
+            ..Self::new(move |_| tag.clone())
+        }
+    }
+
+    pub fn namespace<N: Into<String>>(mut self, namespace: N) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+}
+
+impl<T: Type> KeyCodec<T> for HashTag<T> {
+    fn encode(&self, id: &T::IDType) -> Vec<u8> {
+        let mut key = format!("{}:{}:{{", T::NAME, T::PREFIX).into_bytes();
+
+        key.extend((self.tag)(id));
+        key.extend(b"}:");
+        key.extend(id.to_bytes());
+
+        match &self.namespace {
+            Some(namespace) => namespaced(namespace, key),
+            None => key,
+        }
+    }
+
+    fn decode(&self, key: &[u8]) -> Option<T::IDType> {
+        let key = match &self.namespace {
+            Some(namespace) => key.strip_prefix(namespace.as_bytes())?.strip_prefix(b":")?,
+            None => key,
+        };
+
+        let key = strip_type::<T>(key)?.strip_prefix(b"{")?;
+        let end = key.iter().position(|&b| b == b'}')?;
+
+        KeyId::from_bytes(key[end + 1..].strip_prefix(b":")?)
+    }
+
+    fn index_key(&self, name: &str) -> String {
+        let key = match &self.index_tag {
+            Some(tag) => format!("{}:{}#{{{}}}:idx:{}", T::NAME, T::PREFIX, tag, name),
+            None => KeyCodec::<T>::index_key(&DefaultCodec, name),
+        };
+
+        match &self.namespace {
+            Some(namespace) => format!("{}:{}", namespace, key),
+            None => key,
+        }
+    }
+}
+
+/// `<namespace>:<key>`.
+fn namespaced(namespace: &str, key: Vec<u8>) -> Vec<u8> {
+    let mut namespaced = format!("{}:", namespace).into_bytes();
+
+    namespaced.extend(key);
+    namespaced
+}
+
+/// Rest of `key` after `<name>:<prefix>:`.
+fn strip_type<T: Type>(key: &[u8]) -> Option<&[u8]> {
+    key.strip_prefix(T::NAME.as_bytes())?
+        .strip_prefix(b":")?
+        .strip_prefix(T::PREFIX.as_bytes())?
+        .strip_prefix(b":")
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_module as rm;
+
+    use super::{DefaultCodec, HashTag, KeyCodec, KeyId, Namespaced};
+    use crate::{Loader, Saver, Type};
+
+    struct Item;
+
+    impl Type for Item {
+        type IDType = u64;
+        type Migrations = ();
+
+        const NAME: &'static str = "item";
+        const PREFIX: &'static str = "i";
+
+        const REDIS_NAME: &'static str = "testitem1";
+        const REDIS_VERSION: i32 = 1;
+
+        fn free(_value: Box<Self>) {}
+        fn mem_usage(_value: &Self) -> usize {
+            0
+        }
+        fn rdb_save<S: Saver>(_saver: &S, _value: &Self) {}
+        fn rdb_load<L: Loader>(_loader: &L, _encver: usize) -> Result<Self, rm::error::Error> {
+            Ok(Self)
+        }
+    }
+
+    struct Named;
+
+    impl Type for Named {
+        type IDType = String;
+        type Migrations = ();
+
+        const NAME: &'static str = "named";
+        const PREFIX: &'static str = "n";
+
+        const REDIS_NAME: &'static str = "testname1";
+        const REDIS_VERSION: i32 = 1;
+
+        fn free(_value: Box<Self>) {}
+        fn mem_usage(_value: &Self) -> usize {
+            0
+        }
+        fn rdb_save<S: Saver>(_saver: &S, _value: &Self) {}
+        fn rdb_load<L: Loader>(_loader: &L, _encver: usize) -> Result<Self, rm::error::Error> {
+            Ok(Self)
+        }
+    }
+
+    fn roundtrip<C: KeyCodec<Item>>(codec: &C, id: u64, key: &str) {
+        assert_eq!(codec.encode(&id), key.as_bytes());
+        assert_eq!(codec.decode(key.as_bytes()), Some(id));
+    }
+
+    #[test]
+    fn codecs() {
+        roundtrip(&DefaultCodec, 7, "item:i:7");
+        roundtrip(&Namespaced::new("app"), 7, "app:item:i:7");
+        roundtrip(&HashTag::<Item>::id(), 7, "item:i:{7}:7");
+        roundtrip(
+            &HashTag::<Item>::new(|id| (id % 2).to_string()).namespace("app"),
+            7,
+            "app:item:i:{1}:7",
+        );
+
+        assert_eq!(KeyCodec::<Item>::decode(&DefaultCodec, b"other:i:7"), None);
+        assert_eq!(
+            KeyCodec::<Item>::decode(&Namespaced::new("app"), b"item:i:7"),
+            None
+        );
+        assert_eq!(HashTag::<Item>::fixed("x").decode(b"item:i:7"), None);
+
+        assert_eq!(
+            HashTag::<Item>::fixed("x").index_key("s"),
+            "item:i#{x}:idx:s"
+        );
+        assert_eq!(HashTag::<Item>::id().index_key("s"), "item:i#idx:s");
+    }
+
+    #[test]
+    fn index_keys_apart_from_ids() {
+        let id = "idx:state:pending".to_string();
+        let tag_key = format!(
+            "{}:pending",
+            KeyCodec::<Named>::index_key(&DefaultCodec, "state")
+        );
+
+        assert_eq!(tag_key, "named:n#idx:state:pending");
+        assert_ne!(
+            KeyCodec::<Named>::encode(&DefaultCodec, &id),
+            tag_key.as_bytes()
+        );
+        assert_eq!(
+            KeyCodec::<Named>::decode(&DefaultCodec, tag_key.as_bytes()),
+            None
+        );
+        assert_eq!(
+            HashTag::<Named>::fixed("x").decode(b"named:n#{x}:idx:state"),
+            None
+        );
+    }
+
+    #[test]
+    fn key_ids() {
+        assert_eq!(<[u8; 3]>::from_bytes(&[0, b':', 255]), Some([0, b':', 255]));
+        assert_eq!(<[u8; 3]>::from_bytes(&[0, 255]), None);
+        assert_eq!(vec![0u8, 255].to_bytes(), [0, 255]);
+        assert_eq!(String::from_bytes(&[0xff]), None);
+        assert_eq!((-7i64).to_bytes(), b"-7");
+        assert_eq!(u64::from_bytes(b"x"), None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    ffi,
+    mem,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use redis_module as rm;
+
+/// Set by `module![M, allocator]`, values can only be moved when redis allocated them.
+static REDIS_ALLOC: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)]
+pub fn use_redis_alloc() {
+    REDIS_ALLOC.store(true, Ordering::Relaxed);
+}
+
+/// Whether the defrag callbacks are registered, see [`use_redis_alloc`].
+pub(crate) fn enabled() -> bool {
+    REDIS_ALLOC.load(Ordering::Relaxed)
+}
+
+/// Result of a `Type::defrag` pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Defrag {
+    Done,
+    /// More work left, the position is saved with [`DefragCtx::set_cursor`].
+    Incomplete,
+}
+
+/// Active defrag context, every allocation must come from the redis allocator.
+pub struct DefragCtx {
+    pub(crate) ctx: *mut rm::RedisModuleDefragCtx,
+}
+
+impl DefragCtx {
+    /// Whether the time budget is over and the callback should return `Defrag::Incomplete`.
+    pub fn should_stop(&self) -> bool {
+        unsafe { rm::raw::RedisModule_DefragShouldStop.unwrap()(self.ctx) != 0 }
+    }
+
+    /// Cursor saved by the previous incomplete pass of the same value.
+    ///
+    /// The first pass reads `None` or `Some(0)`, so a saved cursor should not be `0`.
+    pub fn cursor(&self) -> Option<u64> {
+        let mut cursor = 0;
+
+        let status = rm::Status::from(unsafe {
+            rm::raw::RedisModule_DefragCursorGet.unwrap()(self.ctx, &mut cursor)
+        });
+
+        match status {
+            rm::Status::Ok => Some(cursor as u64),
+            rm::Status::Err => None,
+        }
+    }
+
+    pub fn set_cursor(&self, cursor: u64) {
+        unsafe {
+            rm::raw::RedisModule_DefragCursorSet.unwrap()(self.ctx, cursor as _);
+        }
+    }
+
+    /// Moves `ptr` to a less fragmented location, `None` if it was not moved.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be allocated by the redis allocator and not be used after it was moved.
+    pub unsafe fn alloc(&self, ptr: *mut ffi::c_void) -> Option<*mut ffi::c_void> {
+        let moved = rm::raw::RedisModule_DefragAlloc.unwrap()(self.ctx, ptr);
+
+        (!moved.is_null()).then_some(moved)
+    }
+
+    pub fn defrag_box<T>(&self, value: &mut Box<T>) {
+        if mem::size_of::<T>() == 0 {
+            return;
+        }
+
+        let ptr: *mut T = &mut **value;
+
+        if let Some(moved) = unsafe { self.alloc(ptr.cast()) } {
+            // the old allocation is already released, so it must not be dropped
+            unsafe { ptr::write(value, Box::from_raw(moved.cast::<T>())) };
+        }
+    }
+
+    pub fn defrag_vec<T>(&self, value: &mut Vec<T>) {
+        if value.capacity() == 0 || mem::size_of::<T>() == 0 {
+            return;
+        }
+
+        let mut vec = mem::ManuallyDrop::new(mem::take(value));
+        let (ptr, len, capacity) = (vec.as_mut_ptr(), vec.len(), vec.capacity());
+
+        let ptr = match unsafe { self.alloc(ptr.cast()) } {
+            Some(moved) => moved.cast::<T>(),
+            None => ptr,
+        };
+
+        *value = unsafe { Vec::from_raw_parts(ptr, len, capacity) };
+    }
+
+    pub fn defrag_string(&self, value: &mut String) {
+        let mut bytes = mem::take(value).into_bytes();
+
+        self.defrag_vec(&mut bytes);
+
+        *value = unsafe { String::from_utf8_unchecked(bytes) };
+    }
+}
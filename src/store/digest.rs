@@ -0,0 +1,55 @@
+use redis_module as rm;
+
+use crate::Saver;
+
+/// Feeds a value into `DEBUG DIGEST`.
+///
+/// Elements added between two [`Digest::end_sequence`] calls form an ordered sequence,
+/// sequences are mixed in order independently, e.g. one per hash field-value pair.
+pub struct Digest {
+    pub(crate) md: *mut rm::RedisModuleDigest,
+}
+
+impl Digest {
+    pub fn add_string_buffer<S: AsRef<[u8]>>(&self, val: S) {
+        let val = val.as_ref();
+
+        unsafe {
+            rm::raw::RedisModule_DigestAddStringBuffer.unwrap()(
+                self.md,
+                val.as_ptr() as *mut _,
+                val.len(),
+            )
+        }
+    }
+
+    pub fn add_long_long(&self, val: i64) {
+        unsafe { rm::raw::RedisModule_DigestAddLongLong.unwrap()(self.md, val) }
+    }
+
+    pub fn end_sequence(&self) {
+        unsafe { rm::raw::RedisModule_DigestEndSequence.unwrap()(self.md) }
+    }
+}
+
+/// Lets `Type::rdb_save` produce the digest, floats are added by their bits.
+impl Saver for Digest {
+    fn double(&self, val: f64) {
+        self.add_long_long(val.to_bits() as i64)
+    }
+    fn float(&self, val: f32) {
+        self.add_long_long(i64::from(val.to_bits()))
+    }
+    fn unsigned(&self, val: u64) {
+        self.add_long_long(val as i64)
+    }
+    fn signed(&self, val: i64) {
+        self.add_long_long(val)
+    }
+    fn string<S: AsRef<str>>(&self, val: S) {
+        self.add_string_buffer(val.as_ref())
+    }
+    fn buffer<S: AsRef<[u8]>>(&self, val: S) {
+        self.add_string_buffer(val)
+    }
+}
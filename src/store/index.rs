@@ -0,0 +1,313 @@
+use std::{
+    ffi::{self, CString},
+    slice,
+};
+
+use redis_module as rm;
+
+use super::{key::signal_ready, Error, Store, Type};
+
+type Extract<T, V> = Box<dyn Fn(&T) -> V + Send + Sync>;
+
+enum Kind<T> {
+    Numeric(Extract<T, f64>),
+    Tag(Extract<T, String>),
+}
+
+/// Secondary index of a [`Type`], declared by `Type::indexes`.
+///
+/// Indexes are kept up to date by the `EntryMut` methods, except for values changed in place
+/// through `EntryMut::load`, which keep their previous index values until stored again. Values
+/// removed bypassing the store, by `DEL`, expiration or eviction, are skipped by queries. Like a
+/// lazy expire, a query on a master also prunes them from the index and replicates the removal.
+pub struct Index<T> {
+    name: &'static str,
+    kind: Kind<T>,
+}
+
+impl<T> Index<T> {
+    /// Sorted set of ids scored by `extract`, queried with `Store::find_range`.
+    pub fn numeric<F>(name: &'static str, extract: F) -> Self
+    where
+        F: Fn(&T) -> f64 + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            kind: Kind::Numeric(Box::new(extract)),
+        }
+    }
+
+    /// One set of ids per value of `extract`, queried with `Store::find_by`.
+    pub fn tag<F>(name: &'static str, extract: F) -> Self
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        Self {
+            name,
+            kind: Kind::Tag(Box::new(extract)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Value of one index extracted from a stored value.
+#[derive(PartialEq)]
+pub(super) enum Value {
+    Numeric(f64),
+    Tag(String),
+}
+
+/// Values of every index of `store` for `value`, in declaration order.
+pub(super) fn values<T: Type>(store: &Store<T>, value: &T) -> Vec<Value> {
+    store
+        .indexes
+        .iter()
+        .map(|index| match &index.kind {
+            Kind::Numeric(extract) => Value::Numeric(extract(value)),
+            Kind::Tag(extract) => Value::Tag(extract(value)),
+        })
+        .collect()
+}
+
+/// Moves `member` from the index entries of `old` to the ones of `new`.
+pub(super) fn update<T: Type>(
+    ctx: &rm::Context,
+    store: &Store<T>,
+    member: &[u8],
+    old: Option<Vec<Value>>,
+    new: Option<Vec<Value>>,
+) -> Result<(), Error> {
+    for (i, index) in store.indexes.iter().enumerate() {
+        let old = old.as_ref().map(|values| &values[i]);
+        let new = new.as_ref().map(|values| &values[i]);
+
+        if old == new {
+            continue;
+        }
+
+        let key = store.codec.index_key(index.name);
+
+        match old {
+            // the score is replaced by ZADD
+            Some(Value::Numeric(_)) if new.is_some() => {}
+            Some(Value::Numeric(_)) => {
+                call(ctx, "ZREM", &[key.as_bytes(), member])?;
+            }
+            Some(Value::Tag(tag)) => {
+                call(ctx, "SREM", &[tag_key(&key, tag).as_bytes(), member])?;
+            }
+            None => {}
+        }
+
+        match new {
+            Some(Value::Numeric(score)) => {
+                call(
+                    ctx,
+                    "ZADD",
+                    &[key.as_bytes(), score.to_string().as_bytes(), member],
+                )?;
+                signal_ready(ctx, key.as_bytes());
+            }
+            Some(Value::Tag(tag)) => {
+                let key = tag_key(&key, tag);
+
+                call(ctx, "SADD", &[key.as_bytes(), member])?;
+                signal_ready(ctx, key.as_bytes());
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Members read from one index key, with the command removing one of them.
+pub(super) struct Members {
+    key: String,
+    remove: &'static str,
+    pub(super) members: Vec<Vec<u8>>,
+}
+
+impl Members {
+    /// Removes `member`, left behind by a value deleted bypassing the store.
+    ///
+    /// Only masters prune, the removal is replicated as queries may run in read only commands.
+    pub(super) fn prune(&self, ctx: &rm::Context, member: &[u8]) -> Result<(), Error> {
+        if !is_master(ctx) {
+            return Ok(());
+        }
+
+        // "!" replicates the call to replicas and the AOF
+        call_with(ctx, b"!v\0", self.remove, &[self.key.as_bytes(), member]).map(|_| ())
+    }
+}
+
+/// Members of the tag index `index` with `value`.
+pub(super) fn find_by<T: Type>(
+    ctx: &rm::Context,
+    store: &Store<T>,
+    index: &str,
+    value: &str,
+) -> Result<Members, Error> {
+    let key = tag_index_key(store, index, value)?;
+    let members = call(ctx, "SMEMBERS", &[key.as_bytes()])?;
+
+    Ok(Members {
+        key,
+        remove: "SREM",
+        members,
+    })
+}
+
+/// Key of the set of members of the tag index `index` with `value`.
+pub(super) fn tag_index_key<T: Type>(
+    store: &Store<T>,
+    index: &str,
+    value: &str,
+) -> Result<String, Error> {
+    let index = find_index(store, index, |kind| matches!(kind, Kind::Tag(_)))?;
+
+    Ok(tag_key(&store.codec.index_key(index.name), value))
+}
+
+/// Members of the numeric index `index` scored within `min..=max`.
+pub(super) fn find_range<T: Type>(
+    ctx: &rm::Context,
+    store: &Store<T>,
+    index: &str,
+    min: f64,
+    max: f64,
+) -> Result<Members, Error> {
+    let index = find_index(store, index, |kind| matches!(kind, Kind::Numeric(_)))?;
+    let key = store.codec.index_key(index.name);
+
+    let (min, max) = (min.to_string(), max.to_string());
+    let members = call(
+        ctx,
+        "ZRANGEBYSCORE",
+        &[key.as_bytes(), min.as_bytes(), max.as_bytes()],
+    )?;
+
+    Ok(Members {
+        key,
+        remove: "ZREM",
+        members,
+    })
+}
+
+fn find_index<'s, T: Type>(
+    store: &'s Store<T>,
+    name: &str,
+    kind: fn(&Kind<T>) -> bool,
+) -> Result<&'s Index<T>, Error> {
+    store
+        .indexes
+        .iter()
+        .find(|index| index.name == name && kind(&index.kind))
+        .ok_or_else(|| Error::UnknownIndex(name.to_string()))
+}
+
+fn tag_key(key: &str, value: &str) -> String {
+    format!("{}:{}", key, value)
+}
+
+/// Not a replica, nor replaying the AOF or an rdb.
+fn is_master(ctx: &rm::Context) -> bool {
+    let flags = unsafe { rm::raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) } as u32;
+
+    flags & rm::raw::REDISMODULE_CTX_FLAGS_MASTER != 0
+        && flags & rm::raw::REDISMODULE_CTX_FLAGS_LOADING == 0
+}
+
+/// Runs `command args...` with binary safe arguments, unlike `Context::call`, and returns the
+/// strings of an array reply.
+fn call(ctx: &rm::Context, command: &str, args: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+    // "v" is a vector of strings followed by its length
+    call_with(ctx, b"v\0", command, args)
+}
+
+/// Runs `command` like [`call`] with the nul terminated `RM_Call` format `fmt`, ending in `v`.
+fn call_with(
+    ctx: &rm::Context,
+    fmt: &[u8],
+    command: &str,
+    args: &[&[u8]],
+) -> Result<Vec<Vec<u8>>, Error> {
+    let name = CString::new(command).expect("command name without nul");
+
+    let mut argv: Vec<*mut rm::RedisModuleString> = args
+        .iter()
+        .map(|arg| unsafe {
+            rm::raw::RedisModule_CreateString.unwrap()(
+                ctx.ctx,
+                arg.as_ptr().cast::<ffi::c_char>(),
+                arg.len(),
+            )
+        })
+        .collect();
+
+    let reply = unsafe {
+        rm::raw::RedisModule_Call.unwrap()(
+            ctx.ctx,
+            name.as_ptr(),
+            fmt.as_ptr().cast::<ffi::c_char>(),
+            argv.as_mut_ptr(),
+            argv.len(),
+        )
+    };
+
+    for arg in argv {
+        unsafe { rm::raw::RedisModule_FreeString.unwrap()(ctx.ctx, arg) };
+    }
+
+    if reply.is_null() {
+        let err = format!("ERR {} failed", command);
+
+        return Err(Error::Redis(rm::RedisError::String(err)));
+    }
+
+    let strings = unsafe { reply_strings(reply) };
+
+    unsafe { rm::raw::RedisModule_FreeCallReply.unwrap()(reply) };
+
+    strings
+}
+
+/// Strings of an array reply, nothing for other replies but errors.
+unsafe fn reply_strings(reply: *mut rm::RedisModuleCallReply) -> Result<Vec<Vec<u8>>, Error> {
+    match rm::raw::RedisModule_CallReplyType.unwrap()(reply) as u32 {
+        rm::raw::REDISMODULE_REPLY_ERROR => {
+            let err = String::from_utf8_lossy(reply_string(reply)).into_owned();
+
+            Err(Error::Redis(rm::RedisError::String(err)))
+        }
+        rm::raw::REDISMODULE_REPLY_ARRAY => {
+            let len = rm::raw::RedisModule_CallReplyLength.unwrap()(reply);
+
+            Ok((0..len)
+                .map(|i| {
+                    let item = rm::raw::RedisModule_CallReplyArrayElement.unwrap()(reply, i);
+
+                    reply_string(item).to_vec()
+                })
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Bytes of a string or error reply, owned by the reply.
+unsafe fn reply_string<'r>(reply: *mut rm::RedisModuleCallReply) -> &'r [u8] {
+    let mut len = 0;
+
+    let ptr = rm::raw::RedisModule_CallReplyStringPtr.unwrap()(reply, &mut len);
+
+    if ptr.is_null() {
+        return &[];
+    }
+
+    slice::from_raw_parts(ptr.cast::<u8>(), len)
+}
@@ -0,0 +1,105 @@
+use std::{
+    ffi,
+    ptr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use redis_module as rm;
+
+/// Writable key handle for the calls `rm::key::RedisKeyWritable` does not wrap.
+pub(super) struct RawKey {
+    ptr: *mut rm::RedisModuleKey,
+}
+
+impl RawKey {
+    pub(super) fn open(ctx: &rm::Context, name: &[u8]) -> Self {
+        let name = create_string(ctx, name);
+        let mode = rm::raw::REDISMODULE_READ | rm::raw::REDISMODULE_WRITE;
+
+        let ptr = unsafe {
+            rm::raw::RedisModule_OpenKey.unwrap()(ctx.ctx, name.inner, mode as ffi::c_int)
+        };
+
+        Self { ptr: ptr.cast() }
+    }
+
+    /// Time left, `None` for keys without an expire.
+    pub(super) fn ttl(&self) -> Option<Duration> {
+        let ttl = unsafe { rm::raw::RedisModule_GetExpire.unwrap()(self.ptr) };
+
+        (ttl != rm::raw::REDISMODULE_NO_EXPIRE as rm::raw::mstime_t)
+            .then(|| Duration::from_millis(ttl.max(0) as u64))
+    }
+
+    pub(super) fn set_expire(&self, ttl: Option<Duration>) -> rm::Status {
+        let ttl = match ttl {
+            Some(ttl) => ttl.as_millis().try_into().unwrap_or(rm::raw::mstime_t::MAX),
+            None => rm::raw::REDISMODULE_NO_EXPIRE as rm::raw::mstime_t,
+        };
+
+        rm::Status::from(unsafe { rm::raw::RedisModule_SetExpire.unwrap()(self.ptr, ttl) })
+    }
+
+    pub(super) fn set_abs_expire(&self, at: SystemTime) -> rm::Status {
+        let at = at.duration_since(UNIX_EPOCH).map_or(0, |at| {
+            at.as_millis().try_into().unwrap_or(rm::raw::mstime_t::MAX)
+        });
+
+        rm::Status::from(unsafe { rm::raw::RedisModule_SetAbsExpire.unwrap()(self.ptr, at) })
+    }
+
+    /// Detaches the value of type `redis_type`, the key keeps a null value until deleted.
+    ///
+    /// # Safety
+    ///
+    /// The returned pointer is owned by the caller and the type callbacks must accept null.
+    pub(super) unsafe fn detach_value(
+        &self,
+        redis_type: *mut rm::RedisModuleType,
+    ) -> *mut ffi::c_void {
+        let mut value = ptr::null_mut();
+
+        let status = rm::Status::from(rm::raw::RedisModule_ModuleTypeReplaceValue.unwrap()(
+            self.ptr,
+            redis_type,
+            ptr::null_mut(),
+            &mut value,
+        ));
+
+        match status {
+            rm::Status::Ok => value,
+            rm::Status::Err => ptr::null_mut(),
+        }
+    }
+}
+
+/// Redis string of a binary key name.
+pub(crate) fn create_string(ctx: &rm::Context, name: &[u8]) -> rm::RedisString {
+    unsafe {
+        let inner = rm::raw::RedisModule_CreateString.unwrap()(
+            ctx.ctx,
+            name.as_ptr().cast::<ffi::c_char>(),
+            name.len(),
+        );
+
+        // retained by the wrapper, which frees it on drop
+        let string = rm::RedisString::new(ctx.ctx, inner);
+
+        rm::raw::RedisModule_FreeString.unwrap()(ctx.ctx, inner);
+
+        string
+    }
+}
+
+/// Wakes the clients blocked on `name`, see `BlockingRequestHandler`.
+pub(super) fn signal_ready(ctx: &rm::Context, name: &[u8]) {
+    let name = create_string(ctx, name);
+
+    unsafe { rm::raw::RedisModule_SignalKeyAsReady.unwrap()(ctx.ctx, name.inner) };
+}
+
+impl Drop for RawKey {
+    fn drop(&mut self) {
+        unsafe { rm::raw::RedisModule_CloseKey.unwrap()(self.ptr) }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{cmp::Ordering, marker::PhantomData};
+
+use redis_module as rm;
+
+use crate::{Loader, Type};
+
+/// Decodes a [`Type`] from an rdb written with any supported `encver`.
+pub trait Migrations<T> {
+    fn rdb_load<L: Loader>(loader: &L, encver: usize) -> Result<T, rm::error::Error>;
+}
+
+/// No migrations, `Type::rdb_load` receives every `encver` as is.
+impl<T: Type> Migrations<T> for () {
+    fn rdb_load<L: Loader>(loader: &L, encver: usize) -> Result<T, rm::error::Error> {
+        T::rdb_load(loader, encver)
+    }
+}
+
+/// Historic rdb layout of a type, one per `encver`.
+pub trait Schema: Sized {
+    const VERSION: usize;
+
+    /// Layout this one upgrades from, [`Initial`] for the oldest one.
+    type Prev: Schema;
+
+    fn rdb_load<L: Loader>(loader: &L) -> Result<Self, rm::error::Error>;
+    fn upgrade(prev: Self::Prev) -> Result<Self, rm::error::Error>;
+}
+
+/// Upgrade step from the newest historic layout `P` to the current type.
+pub trait Upgrade<P>: Sized {
+    fn upgrade(prev: P) -> Result<Self, rm::error::Error>;
+}
+
+/// Root of every [`Schema`] chain, it has no layout and cannot be loaded.
+pub enum Initial {}
+
+impl Schema for Initial {
+    const VERSION: usize = 0;
+
+    type Prev = Initial;
+
+    fn rdb_load<L: Loader>(_loader: &L) -> Result<Self, rm::error::Error> {
+        Err(rm::error::Error::generic("unsupported encver"))
+    }
+
+    fn upgrade(prev: Self::Prev) -> Result<Self, rm::error::Error> {
+        match prev {}
+    }
+}
+
+/// Migrations through the [`Schema`] chain ending with `P`.
+///
+/// `encver` equal to `Type::REDIS_VERSION` is loaded with `Type::rdb_load`, older ones
+/// with the matching [`Schema::rdb_load`] and upgraded step by step up to the type.
+pub struct MigrateFrom<P>(PhantomData<P>);
+
+impl<T, P> Migrations<T> for MigrateFrom<P>
+where
+    T: Type + Upgrade<P>,
+    P: Schema,
+{
+    fn rdb_load<L: Loader>(loader: &L, encver: usize) -> Result<T, rm::error::Error> {
+        let current = T::REDIS_VERSION as usize;
+
+        match encver.cmp(&current) {
+            Ordering::Equal => T::rdb_load(loader, encver),
+            Ordering::Less => load::<P, L>(loader, encver).and_then(T::upgrade),
+            Ordering::Greater => Err(newer_encver(encver, current)),
+        }
+    }
+}
+
+fn load<S: Schema, L: Loader>(loader: &L, encver: usize) -> Result<S, rm::error::Error> {
+    match encver.cmp(&S::VERSION) {
+        Ordering::Equal => S::rdb_load(loader),
+        Ordering::Less => load::<S::Prev, L>(loader, encver).and_then(S::upgrade),
+        Ordering::Greater => Err(newer_encver(encver, S::VERSION)),
+    }
+}
+
+fn newer_encver(encver: usize, version: usize) -> rm::error::Error {
+    rm::error::Error::generic(&format!(
+        "encver {} is newer than version {}",
+        encver, version
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_module as rm;
+
+    use super::{Initial, MigrateFrom, Migrations, Schema, Upgrade};
+    use crate::{redis_io::memory::MemoryIO, Loader, Saver, Type};
+
+    #[derive(Debug, PartialEq)]
+    struct Item {
+        count: u64,
+        name: String,
+        flag: bool,
+    }
+
+    struct ItemV1 {
+        count: u64,
+    }
+
+    struct ItemV2 {
+        count: u64,
+        name: String,
+    }
+
+    impl Schema for ItemV1 {
+        const VERSION: usize = 1;
+
+        type Prev = Initial;
+
+        fn rdb_load<L: Loader>(loader: &L) -> Result<Self, rm::error::Error> {
+            Ok(Self {
+                count: loader.unsigned()?,
+            })
+        }
+
+        fn upgrade(prev: Self::Prev) -> Result<Self, rm::error::Error> {
+            match prev {}
+        }
+    }
+
+    impl Schema for ItemV2 {
+        const VERSION: usize = 2;
+
+        type Prev = ItemV1;
+
+        fn rdb_load<L: Loader>(loader: &L) -> Result<Self, rm::error::Error> {
+            Ok(Self {
+                count: loader.unsigned()?,
+                name: String::from_utf8_lossy(loader.buffer()?.as_ref()).into_owned(),
+            })
+        }
+
+        fn upgrade(prev: Self::Prev) -> Result<Self, rm::error::Error> {
+            Ok(Self {
+                count: prev.count,
+                name: "v1".to_string(),
+            })
+        }
+    }
+
+    impl Upgrade<ItemV2> for Item {
+        fn upgrade(prev: ItemV2) -> Result<Self, rm::error::Error> {
+            Ok(Self {
+                count: prev.count,
+                name: prev.name,
+                flag: false,
+            })
+        }
+    }
+
+    impl Type for Item {
+        type IDType = u64;
+        type Migrations = MigrateFrom<ItemV2>;
+
+        const NAME: &'static str = "item";
+        const PREFIX: &'static str = "i";
+
+        const REDIS_NAME: &'static str = "testitem3";
+        const REDIS_VERSION: i32 = 3;
+
+        fn free(_value: Box<Self>) {}
+        fn mem_usage(_value: &Self) -> usize {
+            0
+        }
+        fn rdb_save<S: Saver>(saver: &S, value: &Self) {
+            saver.unsigned(value.count);
+            saver.string(&value.name);
+            saver.unsigned(value.flag as u64);
+        }
+        fn rdb_load<L: Loader>(loader: &L, _encver: usize) -> Result<Self, rm::error::Error> {
+            Ok(Self {
+                count: loader.unsigned()?,
+                name: String::from_utf8_lossy(loader.buffer()?.as_ref()).into_owned(),
+                flag: loader.unsigned()? == 1,
+            })
+        }
+    }
+
+    fn load<F: FnOnce(&MemoryIO)>(encver: usize, write: F) -> Option<Item> {
+        let io = MemoryIO::default();
+
+        write(&io);
+
+        <MigrateFrom<ItemV2> as Migrations<Item>>::rdb_load(&io, encver).ok()
+    }
+
+    fn item(name: &str, flag: bool) -> Option<Item> {
+        Some(Item {
+            count: 5,
+            name: name.to_string(),
+            flag,
+        })
+    }
+
+    #[test]
+    fn current() {
+        let loaded = load(3, |io| {
+            Item::rdb_save(io, &item("a", true).unwrap());
+        });
+
+        assert_eq!(loaded, item("a", true));
+    }
+
+    #[test]
+    fn older() {
+        let loaded = load(2, |io| {
+            io.unsigned(5);
+            io.string("b");
+        });
+
+        assert_eq!(loaded, item("b", false));
+        assert_eq!(load(1, |io| io.unsigned(5)), item("v1", false));
+    }
+
+    #[test]
+    fn unsupported() {
+        let newer = load(4, |io| {
+            Item::rdb_save(io, &item("a", true).unwrap());
+            io.unsigned(1);
+        });
+
+        assert_eq!(newer, None);
+        assert_eq!(load(0, |io| io.unsigned(5)), None);
+    }
+}
@@ -1,13 +1,36 @@
+mod aof;
+mod codec;
+mod defrag;
+mod digest;
+mod index;
+mod key;
+mod migrate;
+mod scan;
 mod types;
 
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
 
 use redis_module as rm;
 use redis_module::Context;
 
+pub use aof::AofEmitter;
+pub use codec::{DefaultCodec, HashTag, KeyCodec, KeyId, Namespaced};
+pub use defrag::{use_redis_alloc, Defrag, DefragCtx};
+pub use digest::Digest;
+pub use index::Index;
+pub use migrate::{Initial, MigrateFrom, Migrations, Schema, Upgrade};
+pub use scan::Scan;
 pub use types::{Type, TypeMethods, Types};
 
-use crate::Module;
+use crate::{IntoRedisError, Module};
+
+pub(crate) use key::create_string;
+
+use key::{signal_ready, RawKey};
+use scan::Cursors;
 
 pub trait Stores {
     fn register(&self, _ctx: &rm::Context) -> Result<(), &str>;
@@ -23,15 +46,60 @@ pub type ModuleStores<M> = <<M as Module>::DataTypes as Types>::Stores;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("entity not found")]
-    NotFound,
+    #[error("{entity} {prefix}:{id}")]
+    NotFound {
+        entity: &'static str,
+        prefix: &'static str,
+        id: String,
+    },
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("unknown index: {0}")]
+    UnknownIndex(String),
+    #[error("cannot set expire")]
+    Expire,
+    #[error("value written, its indexes are stale: {0}")]
+    Unindexed(rm::RedisError),
     #[error("redis error: {0}")]
     Redis(rm::RedisError),
 }
 
-pub struct Store<T> {
+impl Error {
+    /// `id` as written by `KeyId::to_bytes`, shown lossily when binary.
+    fn not_found<T: Type>(id: &[u8]) -> Self {
+        Self::NotFound {
+            entity: T::NAME,
+            prefix: T::PREFIX,
+            id: String::from_utf8_lossy(id).into_owned(),
+        }
+    }
+}
+
+impl IntoRedisError for Error {
+    fn code(&self) -> &str {
+        match self {
+            Self::NotFound { .. } => "NOTFOUND",
+            Self::WrongType => "WRONGTYPE",
+            _ => "ERR",
+        }
+    }
+
+    fn into_redis_error(self) -> rm::RedisError {
+        match self {
+            Self::Redis(err) => err,
+            err => rm::RedisError::String(format!("{} {}", err.code(), err)),
+        }
+    }
+}
+
+pub struct Store<T: Type> {
     marker: PhantomData<T>,
     redis_type: rm::native_types::RedisType,
+    codec: Box<dyn KeyCodec<T>>,
+    cursors: Cursors,
+    indexes: Vec<Index<T>>,
 }
 
 unsafe impl<T: Type> Send for Store<T> {}
@@ -41,6 +109,9 @@ impl<T: Type> Default for Store<T> {
         Self {
             marker: PhantomData,
             redis_type: T::redis_type(),
+            codec: Box::new(DefaultCodec),
+            cursors: Cursors::default(),
+            indexes: T::indexes(),
         }
     }
 }
@@ -52,37 +123,120 @@ impl<T: Type + TypeMethods> Store<T> {
         Self {
             redis_type,
             marker: PhantomData,
+            codec: Box::new(DefaultCodec),
+            cursors: Cursors::default(),
+            indexes: T::indexes(),
         }
     }
 
+    /// Replaces the key layout, [`DefaultCodec`] unless set.
+    ///
+    /// Values stored with the previous codec are not moved.
+    pub fn with_codec<C: KeyCodec<T> + 'static>(mut self, codec: C) -> Self {
+        self.codec = Box::new(codec);
+        self
+    }
+
+    pub fn key(&self, id: &T::IDType) -> Vec<u8> {
+        self.codec.encode(id)
+    }
+
+    /// Id of the value stored under `key`, `None` if the key is not in the layout of the store.
+    pub fn id(&self, key: &[u8]) -> Option<T::IDType> {
+        self.codec.decode(key)
+    }
+
     pub fn exists(&self, ctx: &rm::Context, id: &T::IDType) -> Result<bool, Error> {
         self.get(ctx, id).exists()
     }
 
     pub fn get(&self, ctx: &rm::Context, id: &T::IDType) -> Entry<T> {
-        let raw_key = format!("{}:{}:{}", T::NAME, T::PREFIX, id);
+        let raw_key = self.key(id);
 
-        let key = ctx.open_key(&ctx.create_string(&raw_key));
+        let key = ctx.open_key(&create_string(ctx, &raw_key));
 
         Entry {
             key,
             marker: PhantomData,
             redis_type: &self.redis_type,
+            id: id.to_bytes(),
         }
     }
 
-    pub fn get_mut(&self, ctx: &rm::Context, id: &T::IDType) -> EntryMut<T> {
-        let raw_key = format!("{}:{}:{}", T::NAME, T::PREFIX, id);
+    pub fn get_mut<'s>(&'s self, ctx: &'s rm::Context, id: &T::IDType) -> EntryMut<'s, T> {
+        let raw_key = self.key(id);
 
-        let key = ctx.open_key_writable(&ctx.create_string(&raw_key));
+        let key = ctx.open_key_writable(&create_string(ctx, &raw_key));
 
         EntryMut {
             key,
-            marker: PhantomData,
-            redis_type: &self.redis_type,
+            ctx,
+            store: self,
+            member: id.to_bytes(),
+            name: raw_key,
         }
     }
 
+    /// Ids in the tag index `index` with `value`.
+    pub fn find_by(
+        &self,
+        ctx: &rm::Context,
+        index: &str,
+        value: &str,
+    ) -> Result<Vec<T::IDType>, Error> {
+        let members = index::find_by(ctx, self, index, value)?;
+
+        self.existing(ctx, members)
+    }
+
+    /// Ids in the numeric index `index` with a value within `min..=max`, in value order.
+    pub fn find_range(
+        &self,
+        ctx: &rm::Context,
+        index: &str,
+        min: f64,
+        max: f64,
+    ) -> Result<Vec<T::IDType>, Error> {
+        let members = index::find_range(ctx, self, index, min, max)?;
+
+        self.existing(ctx, members)
+    }
+
+    /// Key of the ids with `value` in the tag index `index`, signaled when an id is added.
+    pub fn tag_key(&self, index: &str, value: &str) -> Result<String, Error> {
+        index::tag_index_key(self, index, value)
+    }
+
+    /// Skips the members of values removed bypassing the store, e.g. by `DEL`, expiration or
+    /// eviction. On a master they are also removed from the index, like a lazy expire.
+    fn existing(&self, ctx: &rm::Context, found: index::Members) -> Result<Vec<T::IDType>, Error> {
+        let mut ids = Vec::with_capacity(found.members.len());
+
+        for member in found.members.iter() {
+            let id = match T::IDType::from_bytes(member) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            if self.exists(ctx, &id)? {
+                ids.push(id);
+            } else {
+                found.prune(ctx, member)?;
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Next page of ids of `T` starting at `cursor`, `0` starts a new scan.
+    ///
+    /// A page holds up to `count` ids, it is short, possibly empty, with a non zero cursor once
+    /// about `count * 10` buckets were visited. Like for `SCAN` an id can be returned more than
+    /// once.
+    pub fn scan(&self, ctx: &rm::Context, cursor: u64, count: usize) -> Result<Scan<T>, Error> {
+        scan::scan(self, ctx, cursor, count)
+    }
+
     pub fn register(&self, ctx: &rm::Context) -> Result<(), &str> {
         self.redis_type.create_data_type(ctx.ctx)?;
 
@@ -94,6 +248,8 @@ pub struct Entry<'s, T: Type> {
     marker: PhantomData<&'s T>,
     key: rm::key::RedisKey,
     redis_type: &'s rm::native_types::RedisType,
+    /// Id as written by `KeyId::to_bytes`.
+    id: Vec<u8>,
 }
 
 impl<'s, T: Type> Entry<'s, T> {
@@ -104,15 +260,18 @@ impl<'s, T: Type> Entry<'s, T> {
     pub fn load(&self) -> Result<&T, Error> {
         self.key
             .get_value::<T>(self.redis_type)
-            .map_err(Error::Redis)?
-            .ok_or(Error::NotFound)
+            .map_err(|_| Error::WrongType)?
+            .ok_or_else(|| Error::not_found::<T>(&self.id))
     }
 }
 
 pub struct EntryMut<'s, T: Type> {
-    marker: PhantomData<&'s T>,
     key: rm::key::RedisKeyWritable,
-    redis_type: &'s rm::native_types::RedisType,
+    ctx: &'s rm::Context,
+    store: &'s Store<T>,
+    /// Id as written to the indexes.
+    member: Vec<u8>,
+    name: Vec<u8>,
 }
 
 impl<'s, T: Type> EntryMut<'s, T> {
@@ -122,18 +281,163 @@ impl<'s, T: Type> EntryMut<'s, T> {
 
     pub fn load(&self) -> Result<&mut T, Error> {
         self.key
-            .get_value::<T>(self.redis_type)
-            .map_err(Error::Redis)?
-            .ok_or(Error::NotFound)
+            .get_value::<T>(&self.store.redis_type)
+            .map_err(|_| Error::WrongType)?
+            .ok_or_else(|| Error::not_found::<T>(&self.member))
     }
 
+    /// Replaces the value, the previous expire is cleared and `Type::default_ttl` is applied.
+    ///
+    /// Clients blocked on the key are woken, as are the ones blocked on index keys it joins. The
+    /// value is written before its indexes, `Error::Unindexed` if they fail to follow.
     pub fn store(&self, value: T) -> Result<(), Error> {
+        let prev = self.indexed()?;
+        let next = index::values(self.store, &value);
+        let ttl = T::default_ttl(&value);
+
         self.key
-            .set_value::<T>(self.redis_type, value)
-            .map_err(Error::Redis)
+            .set_value::<T>(&self.store.redis_type, value)
+            .map_err(|_| Error::WrongType)?;
+
+        signal_ready(self.ctx, &self.name);
+
+        self.reindex(prev, Some(next))?;
+
+        match ttl {
+            Some(ttl) => self.set_expire(ttl),
+            None => Ok(()),
+        }
     }
 
     pub fn delete(&self) -> Result<(), Error> {
-        self.key.delete().map_err(Error::Redis).map(|_| ())
+        let prev = self.indexed()?;
+
+        self.key.delete().map_err(Error::Redis)?;
+
+        self.reindex(prev, None)
+    }
+
+    /// Modifies the stored value in place, `Error::NotFound` if there is none.
+    ///
+    /// The expire is kept, unless `Type::default_ttl` returns one for the updated value. Blocked
+    /// clients are woken and index failures reported like by `store`.
+    pub fn update<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let value = self.load()?;
+        let prev = index::values(self.store, value);
+
+        let result = f(value);
+
+        let next = index::values(self.store, value);
+        let ttl = T::default_ttl(value);
+
+        signal_ready(self.ctx, &self.name);
+
+        self.reindex(Some(prev), Some(next))?;
+
+        if let Some(ttl) = ttl {
+            self.set_expire(ttl)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Stored value, the result of `f` is stored first if there is none.
+    pub fn or_insert_with<F>(&self, f: F) -> Result<&mut T, Error>
+    where
+        F: FnOnce() -> T,
+    {
+        if !self.exists()? {
+            self.store(f())?;
+        }
+
+        self.load()
+    }
+
+    /// Removes the stored value and returns it, `Error::NotFound` if there is none.
+    pub fn take(&self) -> Result<T, Error> {
+        let prev = index::values(self.store, self.load()?);
+
+        let raw_type = *self.store.redis_type.raw_type.borrow();
+        let value = unsafe { RawKey::open(self.ctx, &self.name).detach_value(raw_type) };
+
+        if value.is_null() {
+            return Err(Error::not_found::<T>(&self.member));
+        }
+
+        // owned here, the key is deleted with a null value
+        let value = unsafe { Box::from_raw(value.cast::<T>()) };
+
+        self.key.delete().map_err(Error::Redis)?;
+
+        self.reindex(Some(prev), None)?;
+
+        Ok(*value)
+    }
+
+    /// Time left before the value expires, `None` if it does not expire.
+    pub fn ttl(&self) -> Result<Option<Duration>, Error> {
+        self.expect_value()?;
+
+        Ok(RawKey::open(self.ctx, &self.name).ttl())
+    }
+
+    pub fn set_expire(&self, ttl: Duration) -> Result<(), Error> {
+        self.expect_value()?;
+
+        match RawKey::open(self.ctx, &self.name).set_expire(Some(ttl)) {
+            rm::Status::Ok => Ok(()),
+            rm::Status::Err => Err(Error::Expire),
+        }
+    }
+
+    pub fn expire_at(&self, at: SystemTime) -> Result<(), Error> {
+        self.expect_value()?;
+
+        match RawKey::open(self.ctx, &self.name).set_abs_expire(at) {
+            rm::Status::Ok => Ok(()),
+            rm::Status::Err => Err(Error::Expire),
+        }
+    }
+
+    /// Removes the expire, if any.
+    pub fn persist(&self) -> Result<(), Error> {
+        self.expect_value()?;
+
+        match RawKey::open(self.ctx, &self.name).set_expire(None) {
+            rm::Status::Ok => Ok(()),
+            rm::Status::Err => Err(Error::Expire),
+        }
+    }
+
+    fn expect_value(&self) -> Result<(), Error> {
+        self.load().map(|_| ())
+    }
+
+    /// Moves the member to the index entries of `next`, once the value is written.
+    fn reindex(
+        &self,
+        prev: Option<Vec<index::Value>>,
+        next: Option<Vec<index::Value>>,
+    ) -> Result<(), Error> {
+        index::update(self.ctx, self.store, &self.member, prev, next).map_err(|err| match err {
+            Error::Redis(err) => Error::Unindexed(err),
+            err => err,
+        })
+    }
+
+    /// Index values of the stored value, always `None` for types without indexes.
+    fn indexed(&self) -> Result<Option<Vec<index::Value>>, Error> {
+        if self.store.indexes.is_empty() {
+            return Ok(None);
+        }
+
+        match self.load() {
+            Ok(value) => Ok(Some(index::values(self.store, value))),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 }
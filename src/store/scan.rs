@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, ffi, sync::Mutex};
+
+use redis_module as rm;
+
+use crate::guard;
+
+use super::{types::key_bytes, Error, Store, Type};
+
+/// Open cursors above this count are dropped oldest first.
+const MAX_CURSORS: usize = 1024;
+
+/// Buckets visited per requested item, bounds a page over a keyspace of other types.
+const STEPS_PER_ITEM: usize = 10;
+
+/// One page of [`Store::scan`].
+///
+/// Values are not borrowed, they may be gone by the time the page is used, load them with
+/// [`Store::get`].
+pub struct Scan<T: Type> {
+    /// Cursor of the next page, `0` once every key was visited.
+    pub cursor: u64,
+    pub ids: Vec<T::IDType>,
+}
+
+impl<T: Type> Scan<T> {
+    /// `SCAN`-style reply, the next cursor followed by the array of replied ids.
+    pub fn into_reply<F>(self, reply: F) -> rm::RedisValue
+    where
+        F: FnMut(T::IDType) -> rm::RedisValue,
+    {
+        let items = self.ids.into_iter().map(reply).collect();
+
+        rm::RedisValue::Array(vec![
+            rm::RedisValue::BulkString(self.cursor.to_string()),
+            rm::RedisValue::Array(items),
+        ])
+    }
+}
+
+struct Cursor(*mut rm::RedisModuleScanCursor);
+
+unsafe impl Send for Cursor {}
+
+impl Cursor {
+    fn new() -> Self {
+        Self(unsafe { rm::raw::RedisModule_ScanCursorCreate.unwrap()() })
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        unsafe { rm::raw::RedisModule_ScanCursorDestroy.unwrap()(self.0) }
+    }
+}
+
+/// Module scan cursors are opaque, so the ones in progress are kept here by number.
+#[derive(Default)]
+pub(crate) struct Cursors {
+    inner: Mutex<(u64, BTreeMap<u64, Cursor>)>,
+}
+
+impl Cursors {
+    /// A new cursor for `0`, `None` for unknown or expired ones.
+    fn take(&self, id: u64) -> Option<Cursor> {
+        if id == 0 {
+            return Some(Cursor::new());
+        }
+
+        self.inner.lock().unwrap().1.remove(&id)
+    }
+
+    fn put(&self, cursor: Cursor) -> u64 {
+        let (last, open) = &mut *self.inner.lock().unwrap();
+
+        *last = last.checked_add(1).unwrap_or(1);
+        open.insert(*last, cursor);
+
+        if open.len() > MAX_CURSORS {
+            open.pop_first();
+        }
+
+        *last
+    }
+}
+
+struct Visit<'s, T: Type> {
+    store: &'s Store<T>,
+    raw_type: *mut rm::RedisModuleType,
+    ids: Vec<T::IDType>,
+}
+
+pub(super) fn scan<T: Type>(
+    store: &Store<T>,
+    ctx: &rm::Context,
+    cursor: u64,
+    count: usize,
+) -> Result<Scan<T>, Error> {
+    let scan_cursor = store.cursors.take(cursor).ok_or(Error::InvalidCursor)?;
+
+    let mut visit = Visit {
+        store,
+        raw_type: *store.redis_type.raw_type.borrow(),
+        ids: Vec::new(),
+    };
+
+    // every call visits a single bucket, keep going until the page is full or the steps are
+    // used up, a short page keeps the main thread responsive
+    let count = count.max(1);
+    let mut steps = count.saturating_mul(STEPS_PER_ITEM);
+    let mut more = true;
+
+    while more && visit.ids.len() < count && steps > 0 {
+        steps -= 1;
+
+        more = unsafe {
+            rm::raw::RedisModule_Scan.unwrap()(
+                ctx.ctx,
+                scan_cursor.0,
+                Some(visit_key::<T>),
+                &mut visit as *mut Visit<T> as *mut ffi::c_void,
+            )
+        } != 0;
+    }
+
+    let cursor = if more {
+        store.cursors.put(scan_cursor)
+    } else {
+        0
+    };
+
+    Ok(Scan {
+        cursor,
+        ids: visit.ids,
+    })
+}
+
+unsafe extern "C" fn visit_key<T: Type>(
+    ctx: *mut rm::RedisModuleCtx,
+    key_name: *mut rm::RedisModuleString,
+    key: *mut rm::RedisModuleKey,
+    privdata: *mut ffi::c_void,
+) {
+    let visit = &mut *(privdata as *mut Visit<T>);
+
+    // the codec is user code, a key it panics on is skipped
+    let id = guard::catch(format_args!("{} scan", T::NAME), || {
+        visit.store.id(key_bytes(key_name))
+    });
+
+    let id = match id.flatten() {
+        Some(id) => id,
+        None => return,
+    };
+
+    // the key is not passed when it cannot be opened by the scan itself
+    let opened = key.is_null();
+
+    let key = if opened {
+        rm::raw::RedisModule_OpenKey.unwrap()(
+            ctx,
+            key_name,
+            rm::raw::REDISMODULE_READ as ffi::c_int,
+        )
+        .cast::<rm::RedisModuleKey>()
+    } else {
+        key
+    };
+
+    if rm::raw::RedisModule_ModuleTypeGetType.unwrap()(key) == visit.raw_type {
+        visit.ids.push(id);
+    }
+
+    if opened {
+        rm::raw::RedisModule_CloseKey.unwrap()(key);
+    }
+}
@@ -1,11 +1,18 @@
-use std::{ffi, fmt, ptr};
+use std::{ffi, ptr, time::Duration};
 
 use redis_module as rm;
 
-use crate::{IOLoader, IOSaver, Store, Stores};
+use crate::{guard, IOLoader, IOSaver, Loader, Saver, Store, Stores};
+
+use super::{defrag, AofEmitter, Defrag, DefragCtx, Digest, Index, KeyId, Migrations};
 
 pub trait Type: Sized {
-    type IDType: fmt::Display;
+    /// Id of a value, written into its key and read back by the store [`KeyCodec`].
+    ///
+    /// [`KeyCodec`]: super::KeyCodec
+    type IDType: KeyId;
+    /// Decoders for rdb written by older versions of the type, `()` if there are none.
+    type Migrations: Migrations<Self>;
 
     const NAME: &'static str;
     const PREFIX: &'static str;
@@ -15,8 +22,66 @@ pub trait Type: Sized {
 
     fn free(value: Box<Self>);
     fn mem_usage(value: &Self) -> usize;
-    fn rdb_save(saver: &IOSaver, value: &Self);
-    fn rdb_load(loader: &IOLoader, encver: usize) -> Result<Self, rm::error::Error>;
+    fn rdb_save<S: Saver>(saver: &S, value: &Self);
+    fn rdb_load<L: Loader>(loader: &L, encver: usize) -> Result<Self, rm::error::Error>;
+
+    /// Whether `aof_rewrite` is implemented, set along with it.
+    ///
+    /// Without it no rewrite callback is registered rather than one dropping the keys, AOF
+    /// rewrites then need the rdb preamble (`aof-use-rdb-preamble yes`, the default).
+    const HAS_AOF_REWRITE: bool = false;
+
+    /// Emits the commands that rebuild `value` stored under `key`, called on AOF rewrite.
+    ///
+    /// Redis appends the key expire after them, the commands must not set one.
+    fn aof_rewrite(_emitter: &AofEmitter, _key: &[u8], _value: &Self) {}
+
+    /// Effort of freeing `value`, usually the number of allocations it owns.
+    ///
+    /// When lazy freeing applies (`UNLINK`, `FLUSHALL ASYNC`, `lazyfree-lazy-*` configs) values
+    /// with an effort above 64, or `0`, are freed on a background thread, so `free` must not
+    /// call into redis.
+    fn free_effort(_key: &[u8], _value: &Self) -> usize {
+        1
+    }
+
+    /// Called when `key` is removed from the keyspace, before `value` is freed.
+    fn unlink(_key: &[u8], _value: &Self) {}
+
+    /// Adds `value` to `DEBUG DIGEST`, by default everything `rdb_save` writes as one sequence.
+    ///
+    /// The default needs the same order on every server, so no `HashMap` or `HashSet`:
+    /// `#[derive(RedisType)]` rejects them unless a `digest` hook is set.
+    fn digest(digest: &Digest, value: &Self) {
+        Self::rdb_save(digest, value);
+
+        digest.end_sequence();
+    }
+
+    /// Duplicate of `value` for `COPY from_key to_key`, `None` fails the command.
+    ///
+    /// `Clone` types can return `Some(value.clone())`, `#[redis_type(copy)]` generates that.
+    fn copy(_value: &Self, _from_key: &[u8], _to_key: &[u8]) -> Option<Self> {
+        None
+    }
+
+    /// Moves the allocations owned by `value` during active defrag.
+    ///
+    /// `value` itself is moved by the framework before the first pass. Only called when the
+    /// module installs the redis allocator with `module![M, allocator]`.
+    fn defrag(_ctx: &DefragCtx, _key: &[u8], _value: &mut Self) -> Defrag {
+        Defrag::Done
+    }
+
+    /// Expire set every time `value` is stored, `None` keeps it persistent.
+    fn default_ttl(_value: &Self) -> Option<Duration> {
+        None
+    }
+
+    /// Secondary indexes kept by the store, called once when the store is created.
+    fn indexes() -> Vec<Index<Self>> {
+        Vec::new()
+    }
 }
 
 pub trait Types: Sized {
@@ -33,22 +98,34 @@ impl Types for () {
 
 pub unsafe trait TypeMethods {
     fn redis_type() -> rm::native_types::RedisType;
+    fn has_aof_rewrite() -> bool;
     fn type_methods() -> rm::RedisModuleTypeMethods {
         let version: u64 = rm::REDISMODULE_TYPE_METHOD_VERSION.into();
 
         let free: rm::RedisModuleTypeFreeFunc = Some(<Self as TypeMethods>::free);
         let rdb_load: rm::RedisModuleTypeLoadFunc = Some(<Self as TypeMethods>::rdb_load);
         let rdb_save: rm::RedisModuleTypeSaveFunc = Some(<Self as TypeMethods>::rdb_save);
+        let aof_rewrite: rm::RedisModuleTypeRewriteFunc =
+            Self::has_aof_rewrite().then_some(<Self as TypeMethods>::aof_rewrite as _);
+        let mem_usage2: rm::RedisModuleTypeMemUsageFunc2 = Some(<Self as TypeMethods>::mem_usage2);
+        let free_effort2: rm::RedisModuleTypeFreeEffortFunc2 =
+            Some(<Self as TypeMethods>::free_effort2);
+        let unlink2: rm::RedisModuleTypeUnlinkFunc2 = Some(<Self as TypeMethods>::unlink2);
+        let digest: rm::RedisModuleTypeDigestFunc = Some(<Self as TypeMethods>::digest);
+        let copy2: rm::RedisModuleTypeCopyFunc2 = Some(<Self as TypeMethods>::copy2);
+        // moving a value not allocated by redis corrupts the heap
+        let defrag: rm::RedisModuleTypeDefragFunc =
+            defrag::enabled().then_some(<Self as TypeMethods>::defrag as _);
 
         rm::RedisModuleTypeMethods {
             version,
             free,
             rdb_load,
             rdb_save,
-            aof_rewrite: None,
-            // Currently unused by Redis
+            aof_rewrite,
+            // superseded by mem_usage2
             mem_usage: None,
-            digest: None,
+            digest,
             // Aux data
             aux_load: None,
             aux_save: None,
@@ -56,76 +133,123 @@ pub unsafe trait TypeMethods {
             free_effort: None,
             unlink: None,
             copy: None,
-            defrag: None,
+            defrag,
 
-            copy2: None,
-            unlink2: None,
-            free_effort2: None,
-            mem_usage2: None,
+            copy2,
+            unlink2,
+            free_effort2,
+            mem_usage2,
         }
     }
 
     unsafe extern "C" fn free(value: *mut ffi::c_void);
     unsafe extern "C" fn mem_usage(value: *const ffi::c_void) -> usize;
+    unsafe extern "C" fn mem_usage2(
+        _ctx: *mut rm::RedisModuleKeyOptCtx,
+        _value: *const ffi::c_void,
+        _sample_size: usize,
+    ) -> usize;
+    unsafe extern "C" fn free_effort2(
+        _ctx: *mut rm::RedisModuleKeyOptCtx,
+        _value: *const ffi::c_void,
+    ) -> usize;
+    unsafe extern "C" fn unlink2(_ctx: *mut rm::RedisModuleKeyOptCtx, _value: *const ffi::c_void);
     unsafe extern "C" fn rdb_save(_rdb: *mut rm::RedisModuleIO, _value: *mut ffi::c_void);
     unsafe extern "C" fn rdb_load(
         _rdb: *mut rm::RedisModuleIO,
         _encver: ffi::c_int,
     ) -> *mut ffi::c_void;
+    unsafe extern "C" fn aof_rewrite(
+        _aof: *mut rm::RedisModuleIO,
+        _key: *mut rm::RedisModuleString,
+        _value: *mut ffi::c_void,
+    );
+    unsafe extern "C" fn digest(_md: *mut rm::RedisModuleDigest, _value: *mut ffi::c_void);
+    unsafe extern "C" fn copy2(
+        _ctx: *mut rm::RedisModuleKeyOptCtx,
+        _value: *const ffi::c_void,
+    ) -> *mut ffi::c_void;
+    unsafe extern "C" fn defrag(
+        _ctx: *mut rm::RedisModuleDefragCtx,
+        _key: *mut rm::RedisModuleString,
+        _value: *mut *mut ffi::c_void,
+    ) -> ffi::c_int;
 }
 
 unsafe impl<T: Type> TypeMethods for T {
     fn redis_type() -> rm::native_types::RedisType {
-        let version: u64 = rm::REDISMODULE_TYPE_METHOD_VERSION.into();
-
-        let free: rm::RedisModuleTypeFreeFunc = Some(<Self as TypeMethods>::free);
-        let rdb_load: rm::RedisModuleTypeLoadFunc = Some(<Self as TypeMethods>::rdb_load);
-        let rdb_save: rm::RedisModuleTypeSaveFunc = Some(<Self as TypeMethods>::rdb_save);
-
-        let type_methods = rm::RedisModuleTypeMethods {
-            version,
-            free,
-            rdb_load,
-            rdb_save,
-            aof_rewrite: None,
-            // Currently unused by Redis
-            mem_usage: None,
-            digest: None,
-            // Aux data
-            aux_load: None,
-            aux_save: None,
-            aux_save_triggers: 0,
-            free_effort: None,
-            unlink: None,
-            copy: None,
-            defrag: None,
-
-            copy2: None,
-            unlink2: None,
-            free_effort2: None,
-            mem_usage2: None,
-        };
+        let type_methods = <Self as TypeMethods>::type_methods();
 
         rm::native_types::RedisType::new(Self::REDIS_NAME, Self::REDIS_VERSION, type_methods)
     }
 
+    fn has_aof_rewrite() -> bool {
+        T::HAS_AOF_REWRITE
+    }
+
+    // the callbacks run on deletion accept null, left by `EntryMut::take`
     unsafe extern "C" fn free(value: *mut ffi::c_void) {
+        if value.is_null() {
+            return;
+        }
+
         let value = Box::from_raw(value.cast::<T>());
 
-        T::free(value);
+        guard::catch(format_args!("{} free", T::NAME), || T::free(value));
     }
 
     unsafe extern "C" fn mem_usage(value: *const ffi::c_void) -> usize {
         let value = &*value.cast::<T>();
 
-        T::mem_usage(value)
+        guard::catch(format_args!("{} mem usage", T::NAME), || {
+            T::mem_usage(value)
+        })
+        .unwrap_or(0)
+    }
+
+    unsafe extern "C" fn mem_usage2(
+        _ctx: *mut rm::RedisModuleKeyOptCtx,
+        value: *const ffi::c_void,
+        _sample_size: usize,
+    ) -> usize {
+        let value = &*value.cast::<T>();
+
+        guard::catch(format_args!("{} mem usage", T::NAME), || {
+            T::mem_usage(value)
+        })
+        .unwrap_or(0)
+    }
+
+    unsafe extern "C" fn free_effort2(
+        ctx: *mut rm::RedisModuleKeyOptCtx,
+        value: *const ffi::c_void,
+    ) -> usize {
+        let value = match value.cast::<T>().as_ref() {
+            Some(value) => value,
+            None => return 1,
+        };
+
+        guard::catch(format_args!("{} free effort", T::NAME), || {
+            T::free_effort(opt_key_bytes(ctx), value)
+        })
+        .unwrap_or(1)
+    }
+
+    unsafe extern "C" fn unlink2(ctx: *mut rm::RedisModuleKeyOptCtx, value: *const ffi::c_void) {
+        if let Some(value) = value.cast::<T>().as_ref() {
+            guard::catch(format_args!("{} unlink", T::NAME), || {
+                T::unlink(opt_key_bytes(ctx), value)
+            });
+        }
     }
 
     unsafe extern "C" fn rdb_save(rdb: *mut rm::RedisModuleIO, value: *mut ffi::c_void) {
         let saver = IOSaver { rdb };
         let value = &*value.cast::<T>();
 
-        T::rdb_save(&saver, value)
+        guard::catch(format_args!("{} rdb save", T::NAME), || {
+            T::rdb_save(&saver, value)
+        });
     }
 
     unsafe extern "C" fn rdb_load(
@@ -133,14 +257,108 @@ unsafe impl<T: Type> TypeMethods for T {
         encver: ffi::c_int,
     ) -> *mut ffi::c_void {
         let loader = IOLoader { rdb };
+        let encver = encver as usize;
+
+        let loaded = guard::catch(format_args!("{} rdb load", T::NAME), || {
+            <T::Migrations as Migrations<T>>::rdb_load(&loader, encver)
+        });
+
+        let loaded = match loaded {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(err)) => {
+                log::error!(target: T::NAME, "rdb load failed: {}", err);
 
-        let loaded = match T::rdb_load(&loader, encver as usize) {
-            Ok(loaded) => loaded,
-            Err(_err) => return ptr::null_mut(),
+                return ptr::null_mut();
+            }
+            // logged by the guard, a null value fails the load
+            None => return ptr::null_mut(),
         };
 
         Box::into_raw(Box::new(loaded)) as *mut ffi::c_void
     }
+
+    unsafe extern "C" fn aof_rewrite(
+        aof: *mut rm::RedisModuleIO,
+        key: *mut rm::RedisModuleString,
+        value: *mut ffi::c_void,
+    ) {
+        let emitter = AofEmitter { io: aof };
+        let value = &*value.cast::<T>();
+
+        guard::catch(format_args!("{} aof rewrite", T::NAME), || {
+            T::aof_rewrite(&emitter, key_bytes(key), value)
+        });
+    }
+
+    unsafe extern "C" fn digest(md: *mut rm::RedisModuleDigest, value: *mut ffi::c_void) {
+        let digest = Digest { md };
+        let value = &*value.cast::<T>();
+
+        guard::catch(format_args!("{} digest", T::NAME), || {
+            T::digest(&digest, value)
+        });
+    }
+
+    unsafe extern "C" fn copy2(
+        ctx: *mut rm::RedisModuleKeyOptCtx,
+        value: *const ffi::c_void,
+    ) -> *mut ffi::c_void {
+        let value = &*value.cast::<T>();
+
+        let to_key = rm::raw::RedisModule_GetToKeyNameFromOptCtx.unwrap()(ctx);
+
+        let copied = guard::catch(format_args!("{} copy", T::NAME), || {
+            T::copy(value, opt_key_bytes(ctx), key_bytes(to_key as *mut _))
+        });
+
+        match copied.flatten() {
+            Some(copied) => Box::into_raw(Box::new(copied)) as *mut ffi::c_void,
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe extern "C" fn defrag(
+        ctx: *mut rm::RedisModuleDefragCtx,
+        key: *mut rm::RedisModuleString,
+        value: *mut *mut ffi::c_void,
+    ) -> ffi::c_int {
+        let ctx = DefragCtx { ctx };
+
+        // a resumed pass has a cursor, the value was already moved by the first one
+        if matches!(ctx.cursor(), None | Some(0)) {
+            if let Some(moved) = ctx.alloc(*value) {
+                *value = moved;
+            }
+        }
+
+        let value = &mut *(*value).cast::<T>();
+
+        let defrag = guard::catch(format_args!("{} defrag", T::NAME), || {
+            T::defrag(&ctx, key_bytes(key), value)
+        });
+
+        match defrag {
+            Some(Defrag::Incomplete) => 1,
+            // a panicked pass is not resumed
+            Some(Defrag::Done) | None => 0,
+        }
+    }
+}
+
+/// Key name as bytes, `key` is borrowed from redis.
+pub(super) unsafe fn key_bytes<'k>(key: *mut rm::RedisModuleString) -> &'k [u8] {
+    let mut len = 0;
+
+    let ptr = rm::raw::RedisModule_StringPtrLen.unwrap()(key, &mut len);
+
+    std::slice::from_raw_parts(ptr.cast::<u8>(), len)
+}
+
+/// Name of the key a key option callback is called for.
+unsafe fn opt_key_bytes<'k>(ctx: *mut rm::RedisModuleKeyOptCtx) -> &'k [u8] {
+    let key = rm::raw::RedisModule_GetKeyNameFromOptCtx.unwrap()(ctx);
+
+    key_bytes(key as *mut rm::RedisModuleString)
 }
 
 // adapted from core/src/fmt/mod.rs tuple
@@ -176,3 +394,49 @@ macro_rules! peel_types {
 }
 
 tuple_types![T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16,];
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi, ptr};
+
+    use redis_module as rm;
+
+    use crate::{Loader, Saver};
+
+    use super::{Type, TypeMethods};
+
+    struct Faulty;
+
+    impl Type for Faulty {
+        type IDType = u64;
+        type Migrations = ();
+
+        const NAME: &'static str = "faulty";
+        const PREFIX: &'static str = "f";
+
+        const REDIS_NAME: &'static str = "faulty-01";
+        const REDIS_VERSION: i32 = 1;
+
+        fn free(_value: Box<Self>) {}
+
+        fn mem_usage(_value: &Self) -> usize {
+            panic!("mem usage")
+        }
+
+        fn rdb_save<S: Saver>(_saver: &S, _value: &Self) {}
+
+        fn rdb_load<L: Loader>(_loader: &L, _encver: usize) -> Result<Self, rm::error::Error> {
+            panic!("rdb load")
+        }
+    }
+
+    #[test]
+    fn panics_do_not_unwind() {
+        let value = &Faulty as *const Faulty as *const ffi::c_void;
+
+        unsafe {
+            assert_eq!(<Faulty as TypeMethods>::mem_usage(value), 0);
+            assert!(<Faulty as TypeMethods>::rdb_load(ptr::null_mut(), 1).is_null());
+        }
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    collections::HashMap,
+    ffi::{self, CString},
+    mem,
+    ptr,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::OnceCell;
+use redis_module as rm;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    guard,
+    serialize::{from_bytes, to_bytes},
+    CommandArgs,
+    IOLoader,
+    IOSaver,
+    InstanceMngr,
+    Loader,
+    Module,
+    Saver,
+    SerializeError,
+};
+
+/// Id of a timer, kept by persistent timers across restarts.
+pub type TimerId = u64;
+
+type Dispatch = unsafe extern "C" fn(*mut rm::RedisModuleCtx, *mut ffi::c_void);
+
+/// Payload encoding of persistent timers, replicated along with their start and stop.
+struct Codec<P> {
+    encode: fn(&P) -> Result<Vec<u8>, SerializeError>,
+    decode: fn(&[u8]) -> Result<P, SerializeError>,
+}
+
+struct Scheduled<P> {
+    /// Unix time in milliseconds.
+    deadline: u64,
+    payload: P,
+    /// Redis timer, `None` while loading or suppressed on a replica.
+    armed: Option<rm::RedisModuleTimerID>,
+}
+
+struct State<P> {
+    last: TimerId,
+    scheduled: HashMap<TimerId, Scheduled<P>>,
+    /// Redis timers of the timers replaced by `aux_load`, stopped once loading ends.
+    stale: Vec<rm::RedisModuleTimerID>,
+}
+
+/// Timers firing `Module::on_timer` with a payload of type `P`.
+///
+/// Payloads are kept by the registry and redis timers only carry the id, so stopping a timer
+/// that already fired or was stopped does nothing instead of freeing its payload twice.
+///
+/// [`Timers::persistent`] timers are saved with [`Timers::aux_save`], armed again once loading
+/// ends or the server becomes a master, and never fire on replicas. Their start and stop are
+/// replicated, through the `<module>._timers` command, so a promoted replica has them all.
+pub struct Timers<P> {
+    state: Mutex<State<P>>,
+    dispatch: OnceCell<Dispatch>,
+    /// Name of the command applying replicated starts and stops.
+    command: OnceCell<CString>,
+    codec: Option<Codec<P>>,
+}
+
+impl<P> Default for Timers<P> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State {
+                last: 0,
+                scheduled: HashMap::new(),
+                stale: Vec::new(),
+            }),
+            dispatch: OnceCell::new(),
+            command: OnceCell::new(),
+            codec: None,
+        }
+    }
+}
+
+impl<P> Timers<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fires `Module::on_timer` with `payload` after `delay`.
+    ///
+    /// Timers started in `Module::create` are armed once the module is created.
+    pub fn start(&self, ctx: &rm::Context, delay: Duration, payload: P) -> TimerId {
+        let deadline = now().saturating_add(delay.as_millis().try_into().unwrap_or(u64::MAX));
+
+        let mut state = self.state.lock().unwrap();
+
+        state.last += 1;
+
+        let id = state.last;
+        let armed = self.arm(ctx, id, delay);
+
+        if let Some(codec) = &self.codec {
+            match (codec.encode)(&payload) {
+                Ok(payload) => {
+                    let (id, deadline) = (id.to_string(), deadline.to_string());
+
+                    self.replicate(
+                        ctx,
+                        &[b"START", id.as_bytes(), deadline.as_bytes(), &payload],
+                    );
+                }
+                Err(err) => log::error!(target: "timers", "timer {} not replicated: {}", id, err),
+            }
+        }
+
+        state.scheduled.insert(
+            id,
+            Scheduled {
+                deadline,
+                payload,
+                armed,
+            },
+        );
+
+        id
+    }
+
+    /// Stops the timer and returns its payload, `None` if it already fired or was stopped.
+    pub fn stop(&self, ctx: &rm::Context, id: TimerId) -> Option<P> {
+        let scheduled = self.state.lock().unwrap().scheduled.remove(&id)?;
+
+        if let Some(timer) = scheduled.armed {
+            disarm(ctx, timer);
+        }
+
+        self.replicate(ctx, &[b"STOP", id.to_string().as_bytes()]);
+
+        Some(scheduled.payload)
+    }
+
+    /// Stops every timer and returns their payloads.
+    pub fn stop_all(&self, ctx: &rm::Context) -> Vec<P> {
+        let scheduled = mem::take(&mut self.state.lock().unwrap().scheduled);
+
+        self.replicate(ctx, &[b"CLEAR"]);
+
+        scheduled
+            .into_values()
+            .map(|scheduled| {
+                if let Some(timer) = scheduled.armed {
+                    disarm(ctx, timer);
+                }
+
+                scheduled.payload
+            })
+            .collect()
+    }
+
+    pub fn is_live(&self, id: TimerId) -> bool {
+        self.state.lock().unwrap().scheduled.contains_key(&id)
+    }
+
+    /// Time left before the timer fires, `None` if it is not live.
+    pub fn remaining(&self, id: TimerId) -> Option<Duration> {
+        let deadline = self.state.lock().unwrap().scheduled.get(&id)?.deadline;
+
+        Some(Duration::from_millis(deadline.saturating_sub(now())))
+    }
+
+    /// Arms every timer not armed yet, overdue ones fire right away.
+    fn arm_all(&self, ctx: &rm::Context) {
+        let mut state = self.state.lock().unwrap();
+        let now = now();
+
+        for timer in mem::take(&mut state.stale) {
+            disarm(ctx, timer);
+        }
+
+        for (&id, scheduled) in state.scheduled.iter_mut() {
+            if scheduled.armed.is_none() {
+                let delay = Duration::from_millis(scheduled.deadline.saturating_sub(now));
+
+                scheduled.armed = self.arm(ctx, id, delay);
+            }
+        }
+    }
+
+    /// Stops the redis timers, the timers are kept to be armed again.
+    fn disarm_all(&self, ctx: &rm::Context) {
+        let mut state = self.state.lock().unwrap();
+
+        for scheduled in state.scheduled.values_mut() {
+            if let Some(timer) = scheduled.armed.take() {
+                disarm(ctx, timer);
+            }
+        }
+    }
+
+    fn arm(
+        &self,
+        ctx: &rm::Context,
+        id: TimerId,
+        delay: Duration,
+    ) -> Option<rm::RedisModuleTimerID> {
+        let dispatch = *self.dispatch.get()?;
+
+        if self.codec.is_some() && is_replica(ctx) {
+            return None;
+        }
+
+        let timer = unsafe {
+            rm::raw::RedisModule_CreateTimer.unwrap()(
+                ctx.ctx,
+                delay
+                    .as_millis()
+                    .try_into()
+                    .unwrap_or(rm::raw::mstime_t::MAX),
+                Some(dispatch),
+                id as usize as *mut ffi::c_void,
+            )
+        };
+
+        Some(timer)
+    }
+
+    /// Payload of a fired timer, `None` if it was stopped meanwhile.
+    fn fired(&self, id: TimerId) -> Option<P> {
+        let scheduled = self.state.lock().unwrap().scheduled.remove(&id)?;
+
+        Some(scheduled.payload)
+    }
+
+    /// Sends `<module>._timers args...` to the replicas and the AOF, for persistent timers.
+    fn replicate(&self, ctx: &rm::Context, args: &[&[u8]]) {
+        let command = match (&self.codec, self.command.get()) {
+            (Some(_), Some(command)) => command,
+            _ => return,
+        };
+
+        let mut argv: Vec<*mut rm::RedisModuleString> = args
+            .iter()
+            .map(|arg| unsafe {
+                rm::raw::RedisModule_CreateString.unwrap()(
+                    ptr::null_mut(),
+                    arg.as_ptr().cast::<ffi::c_char>(),
+                    arg.len(),
+                )
+            })
+            .collect();
+
+        // "v" is a vector of strings followed by its length
+        let status = rm::Status::from(unsafe {
+            rm::raw::RedisModule_Replicate.unwrap()(
+                ctx.ctx,
+                command.as_ptr(),
+                b"v\0".as_ptr().cast::<ffi::c_char>(),
+                argv.as_mut_ptr(),
+                argv.len(),
+            )
+        });
+
+        if status == rm::Status::Err {
+            log::error!(target: "timers", "cannot replicate timer {:?}", args.first());
+        }
+
+        for arg in argv {
+            unsafe { rm::raw::RedisModule_FreeString.unwrap()(ptr::null_mut(), arg) };
+        }
+    }
+
+    /// Applies a start or stop replicated by [`Timers::replicate`].
+    fn apply(&self, ctx: &rm::Context, mut args: CommandArgs) -> Result<(), rm::RedisError> {
+        let codec = self
+            .codec
+            .as_ref()
+            .ok_or(rm::RedisError::Str("ERR timers are not persistent"))?;
+
+        let action: String = args.parse("action")?;
+
+        let mut state = self.state.lock().unwrap();
+
+        let replaced: Vec<Scheduled<P>> = match action.to_ascii_uppercase().as_str() {
+            "START" => {
+                let id: TimerId = args.parse("id")?;
+                let deadline = args.parse("deadline")?;
+                let payload = (codec.decode)(&args.bytes("payload")?).map_err(|err| {
+                    rm::RedisError::String(format!("ERR invalid payload: {}", err))
+                })?;
+
+                args.finish()?;
+
+                state.last = state.last.max(id);
+
+                // armed once loading ends or the server becomes a master
+                let scheduled = Scheduled {
+                    deadline,
+                    payload,
+                    armed: None,
+                };
+
+                state.scheduled.insert(id, scheduled).into_iter().collect()
+            }
+            "STOP" => {
+                let id: TimerId = args.parse("id")?;
+
+                args.finish()?;
+
+                state.scheduled.remove(&id).into_iter().collect()
+            }
+            "CLEAR" => {
+                args.finish()?;
+
+                mem::take(&mut state.scheduled).into_values().collect()
+            }
+            _ => {
+                return Err(rm::RedisError::String(format!(
+                    "ERR unknown action {}",
+                    action
+                )))
+            }
+        };
+
+        for scheduled in replaced {
+            if let Some(timer) = scheduled.armed {
+                disarm(ctx, timer);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: Serialize + DeserializeOwned> Timers<P> {
+    /// Timers surviving restarts and failovers, the module persists them in its aux data.
+    pub fn persistent() -> Self {
+        Self {
+            codec: Some(Codec {
+                encode: to_bytes::<P>,
+                decode: from_bytes::<P>,
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// Writes the timers with their deadlines, called from `Module::aux_save`.
+    ///
+    /// Payloads are encoded before anything is written, timers whose payload fails to encode
+    /// are logged and left out instead of truncating the aux data.
+    pub fn aux_save(&self, saver: &IOSaver) -> Result<(), SerializeError> {
+        let state = self.state.lock().unwrap();
+
+        let encoded: Vec<_> = state
+            .scheduled
+            .iter()
+            .filter_map(|(&id, scheduled)| match to_bytes(&scheduled.payload) {
+                Ok(payload) => Some((id, scheduled.deadline, payload)),
+                Err(err) => {
+                    log::error!(target: "timers", "timer {} not saved: {}", id, err);
+                    None
+                }
+            })
+            .collect();
+
+        saver.unsigned(encoded.len() as u64);
+
+        for (id, deadline, payload) in encoded {
+            saver.unsigned(id);
+            saver.unsigned(deadline);
+            saver.buffer(payload);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the timers with the ones written by `aux_save`, called from `Module::aux_load`.
+    ///
+    /// The loaded timers are armed once loading ends.
+    pub fn aux_load(&self, loader: &IOLoader) -> Result<(), SerializeError> {
+        let len = loader.unsigned()?;
+        let mut loaded = HashMap::new();
+
+        for _ in 0..len {
+            let id = loader.unsigned()?;
+            let deadline = loader.unsigned()?;
+            let payload = from_bytes(loader.buffer()?.as_ref())?;
+
+            loaded.insert(
+                id,
+                Scheduled {
+                    deadline,
+                    payload,
+                    armed: None,
+                },
+            );
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        state.last = loaded.keys().copied().fold(state.last, u64::max);
+
+        let replaced = mem::replace(&mut state.scheduled, loaded);
+
+        state.stale.extend(
+            replaced
+                .into_values()
+                .filter_map(|scheduled| scheduled.armed),
+        );
+
+        Ok(())
+    }
+}
+
+/// Binds the timers of the module, if any, to `dispatch::<M, G>` and arms them.
+///
+/// Persistent timers also get the `<module>._timers` command applying replicated changes.
+pub(crate) fn bind<M, G>(ctx: &rm::Context, module: &M) -> Result<(), String>
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let timers = match module.timers() {
+        Some(timers) => timers,
+        None => return Ok(()),
+    };
+
+    // a module instance is created once, a second bind keeps the first callback
+    let _ = timers.dispatch.set(dispatch::<M, G>);
+
+    timers.arm_all(ctx);
+
+    if timers.codec.is_none() {
+        return Ok(());
+    }
+
+    let name = format!("{}._timers", M::NAME);
+    let command = CString::new(name.as_str()).map_err(|_| format!("invalid command {}", name))?;
+    let flags = CString::new("write").unwrap();
+
+    let status = rm::Status::from(unsafe {
+        rm::raw::RedisModule_CreateCommand.unwrap()(
+            ctx.ctx,
+            command.as_ptr(),
+            Some(replicated::<M, G>),
+            flags.as_ptr(),
+            0,
+            0,
+            0,
+        )
+    });
+
+    if status == rm::Status::Err {
+        return Err(format!("cannot register command {}", name));
+    }
+
+    let _ = timers.command.set(command);
+
+    let events = [
+        rm::raw::REDISMODULE_EVENT_LOADING,
+        rm::raw::REDISMODULE_EVENT_REPLICATION_ROLE_CHANGED,
+    ];
+
+    for id in events {
+        let event = rm::RedisModuleEvent {
+            id: id as u64,
+            dataver: 1,
+        };
+
+        let status = rm::Status::from(unsafe {
+            rm::raw::RedisModule_SubscribeToServerEvent.unwrap()(
+                ctx.ctx,
+                event,
+                Some(on_server_event::<M, G>),
+            )
+        });
+
+        if status == rm::Status::Err {
+            return Err(format!("cannot subscribe to server event {}", id));
+        }
+    }
+
+    Ok(())
+}
+
+unsafe extern "C" fn dispatch<M, G>(ctx: *mut rm::RedisModuleCtx, data: *mut ffi::c_void)
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let instance = match G::get() {
+        Some(instance) => instance,
+        None => return,
+    };
+
+    let timers = match instance.timers() {
+        Some(timers) => timers,
+        None => return,
+    };
+
+    let id = data as TimerId;
+
+    let payload = match timers.fired(id) {
+        Some(payload) => payload,
+        None => return,
+    };
+
+    let ctx = rm::Context::new(ctx);
+
+    timers.replicate(&ctx, &[b"STOP", id.to_string().as_bytes()]);
+
+    guard::catch("timer", || instance.on_timer(&ctx, payload));
+}
+
+/// `<module>._timers START <id> <deadline> <payload> | STOP <id> | CLEAR`, only run from the
+/// replication stream or the AOF.
+extern "C" fn replicated<M, G>(
+    ctx: *mut rm::RedisModuleCtx,
+    argv: *mut *mut rm::RedisModuleString,
+    argc: ffi::c_int,
+) -> ffi::c_int
+where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let ctx = &rm::Context::new(ctx);
+
+    let applied = guard::catch("timers", || {
+        if !is_replicated(ctx) {
+            return Err(rm::RedisError::Str("ERR internal command"));
+        }
+
+        let timers = G::get()
+            .and_then(|instance| instance.timers())
+            .ok_or(rm::RedisError::Str("ERR instance missed"))?;
+
+        timers.apply(ctx, CommandArgs::new(rm::decode_args(ctx.ctx, argv, argc)))?;
+
+        Ok(rm::RedisValue::SimpleStringStatic("OK"))
+    });
+
+    match applied {
+        Some(result) => ctx.reply(result) as ffi::c_int,
+        None => ctx.reply(guard::panicked("timers")) as ffi::c_int,
+    }
+}
+
+unsafe extern "C" fn on_server_event<M, G>(
+    ctx: *mut rm::RedisModuleCtx,
+    event: rm::RedisModuleEvent,
+    subevent: u64,
+    _data: *mut ffi::c_void,
+) where
+    M: Module + 'static,
+    G: InstanceMngr<M>,
+{
+    let timers = match G::get().and_then(|instance| instance.timers()) {
+        Some(timers) => timers,
+        None => return,
+    };
+
+    let ctx = rm::Context::new(ctx);
+
+    guard::catch("server event", || {
+        match (event.id as u32, subevent as u32) {
+            (rm::raw::REDISMODULE_EVENT_LOADING, rm::raw::REDISMODULE_SUBEVENT_LOADING_ENDED) => {
+                timers.arm_all(&ctx)
+            }
+            (
+                rm::raw::REDISMODULE_EVENT_REPLICATION_ROLE_CHANGED,
+                rm::raw::REDISMODULE_EVENT_REPLROLECHANGED_NOW_MASTER,
+            ) => timers.arm_all(&ctx),
+            (
+                rm::raw::REDISMODULE_EVENT_REPLICATION_ROLE_CHANGED,
+                rm::raw::REDISMODULE_EVENT_REPLROLECHANGED_NOW_REPLICA,
+            ) => timers.disarm_all(&ctx),
+            _ => {}
+        }
+    });
+}
+
+fn disarm(ctx: &rm::Context, timer: rm::RedisModuleTimerID) {
+    // the timer data is the id, there is nothing to free
+    let mut data = ptr::null_mut();
+
+    unsafe { rm::raw::RedisModule_StopTimer.unwrap()(ctx.ctx, timer, &mut data) };
+}
+
+fn is_replica(ctx: &rm::Context) -> bool {
+    let flags = unsafe { rm::raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) };
+
+    flags as u32 & rm::raw::REDISMODULE_CTX_FLAGS_SLAVE != 0
+}
+
+/// Sent by the master or replayed from the AOF.
+fn is_replicated(ctx: &rm::Context) -> bool {
+    let flags = unsafe { rm::raw::RedisModule_GetContextFlags.unwrap()(ctx.ctx) } as u32;
+
+    flags & (rm::raw::REDISMODULE_CTX_FLAGS_REPLICATED | rm::raw::REDISMODULE_CTX_FLAGS_LOADING)
+        != 0
+}
+
+/// Unix time in milliseconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ptr, time::Duration};
+
+    use redis_module as rm;
+
+    use super::Timers;
+
+    // without a bound dispatch the timers are never armed, redis is not called
+
+    #[test]
+    fn stop_is_idempotent() {
+        let ctx = rm::Context::new(ptr::null_mut());
+        let timers = Timers::new();
+
+        let id = timers.start(&ctx, Duration::from_secs(60), "task");
+
+        assert!(timers.is_live(id));
+        assert_eq!(timers.stop(&ctx, id), Some("task"));
+        assert_eq!(timers.stop(&ctx, id), None);
+        assert!(!timers.is_live(id));
+        assert_eq!(timers.remaining(id), None);
+    }
+
+    #[test]
+    fn fired_once() {
+        let ctx = rm::Context::new(ptr::null_mut());
+        let timers = Timers::new();
+
+        let first = timers.start(&ctx, Duration::from_secs(60), 1);
+        let second = timers.start(&ctx, Duration::from_secs(60), 2);
+
+        assert_ne!(first, second);
+        assert!(timers.remaining(first).unwrap() <= Duration::from_secs(60));
+        assert!(timers.remaining(first).unwrap() > Duration::from_secs(50));
+
+        assert_eq!(timers.stop(&ctx, first), Some(1));
+        assert_eq!(timers.fired(first), None);
+
+        assert_eq!(timers.fired(second), Some(2));
+        assert_eq!(timers.fired(second), None);
+        assert_eq!(timers.stop(&ctx, second), None);
+    }
+}